@@ -12,21 +12,23 @@ impl AstPrinter for Expr {
                 operator,
                 right,
             } => parenthesize(&operator.lexeme, &vec![left, right]),
-            Expr::Grouping { expression } => parenthesize("group", &vec![expression]),
-            Expr::Literal { value } => value.to_string(),
+            Expr::Grouping { expression, .. } => parenthesize("group", &vec![expression]),
+            Expr::Literal { value, .. } => value.to_string(),
             Expr::Unary { operator, right } => parenthesize(&operator.lexeme, &vec![right]),
-            Expr::Assign { name, value } => todo!(),
-            Expr::Variable { name } => todo!(),
-            Expr::Logical {
-                left,
-                operator,
-                right,
-            } => todo!(),
-            Expr::Call {
-                callee,
-                paren,
-                arguments,
-            } => todo!(),
+            Expr::Assign { .. } => todo!(),
+            Expr::Variable { .. } => todo!(),
+            Expr::Logical { .. } => todo!(),
+            Expr::Call { .. } => todo!(),
+            Expr::Function { .. } => todo!(),
+            Expr::Get { .. } => todo!(),
+            Expr::Set { .. } => todo!(),
+            Expr::Index { .. } => todo!(),
+            Expr::Interpolation { .. } => todo!(),
+            Expr::List { .. } => todo!(),
+            Expr::Map { .. } => todo!(),
+            Expr::SetIndex { .. } => todo!(),
+            Expr::Super { .. } => todo!(),
+            Expr::This { .. } => todo!(),
         }
     }
 }
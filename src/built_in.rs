@@ -1,28 +1,24 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::{
-    environment::Environment,
-    interpreter::Interpreter,
-    lox_callable::{BuiltInFunction, LoxCallable},
-    token::Value,
-};
+use anyhow::Result;
 
-pub fn register_builtins(environment: &mut Environment) {
-    environment.define(
-        "clock".to_string(),
-        Value::Callable(LoxCallable::BuiltIn(BuiltInFunction {
-            name: "clock".to_string(),
-            arity: 0,
-            func: clock,
-        })),
-    );
+use crate::{interpreter::Interpreter, lox_callable::CallContext, token::Value};
+
+pub fn register_builtins(interpreter: &mut Interpreter) {
+    interpreter.define_native("clock", 0, clock);
+    interpreter.define_native("Error", 1, error);
+}
+
+fn clock(_: &mut CallContext, _: &[Value]) -> Result<Value> {
+    Ok(Value::Number(
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as f64,
+    ))
 }
 
-fn clock(_: &Interpreter, _: &[Value]) -> Value {
-    Value::Number(
-        SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as f64,
-    )
+/// Creates an error value like the ones runtime errors turn into when caught, for scripts to
+/// throw themselves.
+fn error(context: &mut CallContext, arguments: &[Value]) -> Result<Value> {
+    Ok(context
+        .interpreter()
+        .error_value(arguments[0].clone(), context.line()))
 }
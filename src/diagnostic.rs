@@ -0,0 +1,31 @@
+use std::ops::Range;
+
+/// Renders the source line containing `span` with the spanned text underlined as `^~~~`.
+/// Spans that run past the end of the line are cut off at the line break.
+pub fn underline(source: &str, span: &Range<usize>) -> String {
+    let start = span.start.min(source.len());
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[start..]
+        .find('\n')
+        .map_or(source.len(), |i| start + i);
+    let line_number = source[..line_start].matches('\n').count() + 1;
+
+    let text = source[line_start..line_end].trim_end_matches('\r');
+    let padding: String = source[line_start..start]
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let end = span.end.clamp(start, line_end);
+    let width = source[start..end].chars().count().max(1);
+
+    let gutter = " ".repeat(line_number.to_string().len());
+    format!(
+        "{} |\n{} | {}\n{} | {}^{}\n",
+        gutter,
+        line_number,
+        text,
+        gutter,
+        padding,
+        "~".repeat(width - 1)
+    )
+}
@@ -1,58 +1,135 @@
-use std::{
-    cell::RefCell,
-    collections::HashMap,
-    sync::{Arc, Mutex},
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    expr::Slot,
+    heap::{Allocation, Heap},
+    interpreter::RuntimeError,
+    token::{Token, Value},
 };
 
-use crate::token::{Token, Value};
+/// Bytes charged to the heap for each variable.
+const VARIABLE_SIZE: usize = std::mem::size_of::<Value>();
 
+/// A local scope. Variables are stored in declaration order, so the resolver can address them by
+/// slot index instead of by name. Cloning an `Environment` shares the scope.
 #[derive(Clone, Debug)]
 pub struct Environment {
-    enclosing: Option<Arc<Mutex<RefCell<Environment>>>>,
-    values: Arc<Mutex<RefCell<HashMap<String, Value>>>>,
+    scope: Rc<Scope>,
+}
+
+#[derive(Debug)]
+struct Scope {
+    enclosing: Option<Environment>,
+    values: RefCell<Vec<Value>>,
+    allocation: Allocation,
 }
 
 impl Environment {
-    pub fn new(enclosing: Option<Environment>) -> Self {
+    /// Creates a scope whose variables will be charged to `heap`.
+    pub fn new(enclosing: Option<Environment>, heap: Option<&Rc<Heap>>) -> Self {
         Self {
-            enclosing: enclosing.map(|e| Arc::new(Mutex::new(RefCell::new(e)))),
-            values: Arc::new(Mutex::new(RefCell::new(HashMap::new()))),
+            scope: Rc::new(Scope {
+                enclosing,
+                values: RefCell::new(vec![]),
+                allocation: Allocation::empty(heap),
+            }),
         }
     }
 
-    pub fn define(&self, name: String, value: Value) {
-        let map_lock = self.values.lock().unwrap();
-        let mut map = map_lock.borrow_mut();
-        map.insert(name, value);
-    }
-
-    pub fn get(&self, name: &Token) -> Value {
-        if let Some(value) = self.values.lock().unwrap().borrow().get(&name.lexeme) {
-            value.clone()
-        } else if let Some(enclosing) = &self.enclosing {
-            let enclosing = enclosing.lock().unwrap();
-            let wrapper = enclosing.borrow();
-            wrapper.get(name)
-        } else {
-            panic!("Undefined variable: {}", name.lexeme);
+    pub fn define(&self, value: Value) -> Result<()> {
+        self.scope.allocation.grow(VARIABLE_SIZE)?;
+        self.scope.values.borrow_mut().push(value);
+        Ok(())
+    }
+
+    pub fn get_at(&self, slot: Slot) -> Value {
+        self.ancestor(slot.depth).scope.values.borrow()[slot.index].clone()
+    }
+
+    pub fn assign_at(&self, slot: Slot, value: Value) {
+        self.ancestor(slot.depth).scope.values.borrow_mut()[slot.index] = value;
+    }
+
+    fn ancestor(&self, distance: usize) -> &Environment {
+        let mut environment = self;
+        for _ in 0..distance {
+            environment = environment
+                .scope
+                .enclosing
+                .as_ref()
+                .expect("Resolved scope depth exceeds environment chain");
         }
+        environment
     }
 
-    pub fn assign(&self, name: &Token, value: Value) {
-        let map_lock = self.values.lock().unwrap();
-        let mut map = map_lock.borrow_mut();
-        if map.contains_key(&name.lexeme) {
-            if let Some(old_value) = map.get_mut(&name.lexeme) {
-                *old_value = value;
-                return;
+    /// Moves this scope's variables, and those of any enclosing scopes only it holds on to, into
+    /// `pending`. See [`Value::take_contents`].
+    pub(crate) fn take_contents(&mut self, pending: &mut Vec<Value>) {
+        let mut next = self.take_scope_contents(pending);
+        while let Some(mut environment) = next {
+            next = environment.take_scope_contents(pending);
+        }
+    }
+
+    fn take_scope_contents(&mut self, pending: &mut Vec<Value>) -> Option<Environment> {
+        let scope = Rc::get_mut(&mut self.scope)?;
+        pending.append(scope.values.get_mut());
+        scope.enclosing.take()
+    }
+}
+
+impl Drop for Environment {
+    fn drop(&mut self) {
+        // Reuses the variables' own storage for the values left to drop.
+        if let Some(scope) = Rc::get_mut(&mut self.scope) {
+            let mut pending = std::mem::take(scope.values.get_mut());
+            if let Some(mut enclosing) = scope.enclosing.take() {
+                enclosing.take_contents(&mut pending);
             }
+            Value::drop_all(pending);
         }
+    }
+}
+
+/// Variables defined at the top level, looked up by name because they can be referenced before
+/// they are declared.
+#[derive(Clone, Debug, Default)]
+pub struct Globals {
+    values: Rc<RefCell<HashMap<String, Value>>>,
+}
+
+impl Globals {
+    pub fn define(&self, name: String, value: Value) {
+        self.values.borrow_mut().insert(name, value);
+    }
 
-        if let Some(enclosing) = &self.enclosing {
-            let enclosing = enclosing.lock().unwrap();
-            enclosing.borrow_mut().assign(name, value);
+    pub fn get(&self, name: &Token) -> Result<Value> {
+        if let Some(value) = self.lookup(&name.lexeme) {
+            Ok(value)
         } else {
-            panic!("Undefined variable {}", name.lexeme);
+            bail!(RuntimeError::new(
+                name,
+                &format!("Undefined variable '{}'.", name.lexeme)
+            ))
+        }
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<Value> {
+        self.values.borrow().get(name).cloned()
+    }
+
+    pub fn assign(&self, name: &Token, value: Value) -> Result<()> {
+        match self.values.borrow_mut().get_mut(&name.lexeme) {
+            Some(old_value) => {
+                *old_value = value;
+                Ok(())
+            }
+            None => bail!(RuntimeError::new(
+                name,
+                &format!("Undefined variable '{}'.", name.lexeme)
+            )),
         }
     }
 }
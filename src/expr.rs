@@ -1,10 +1,24 @@
-use crate::token::{Token, Value};
+use std::{ops::Range, rc::Rc};
+
+use crate::{
+    stmt::Stmt,
+    token::{Token, Value},
+};
+
+/// Location of a resolved local variable: `depth` environments out from the current one, at
+/// index `index` within that environment.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
 
 #[derive(Clone)]
 pub enum Expr {
     Assign {
         name: Token,
         value: Box<Expr>,
+        slot: Option<Slot>,
     },
     Binary {
         left: Box<Expr>,
@@ -16,22 +30,111 @@ pub enum Expr {
         paren: Token,
         arguments: Vec<Expr>,
     },
+    /// An anonymous function, from either `fun (a) { ... }` or `(a) => ...`. An arrow function's
+    /// body is a single `return` of its expression.
+    Function {
+        keyword: Token,
+        params: Vec<Token>,
+        body: Rc<[Stmt]>,
+        span: Range<usize>,
+    },
+    Get {
+        object: Box<Expr>,
+        name: Token,
+    },
     Grouping {
         expression: Box<Expr>,
+        span: Range<usize>,
+    },
+    Index {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+    },
+    /// An interpolated string: the string segments and interpolated expressions in order, each
+    /// converted to a string and concatenated.
+    Interpolation {
+        /// The first string segment, where errors building the string are reported.
+        start: Token,
+        parts: Vec<Expr>,
+        span: Range<usize>,
+    },
+    List {
+        bracket: Token,
+        elements: Vec<Expr>,
+        span: Range<usize>,
     },
     Literal {
         value: Value,
+        span: Range<usize>,
     },
     Logical {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
     },
+    Map {
+        brace: Token,
+        entries: Vec<(Expr, Expr)>,
+        span: Range<usize>,
+    },
+    Set {
+        object: Box<Expr>,
+        name: Token,
+        value: Box<Expr>,
+    },
+    SetIndex {
+        object: Box<Expr>,
+        bracket: Token,
+        index: Box<Expr>,
+        value: Box<Expr>,
+    },
+    Super {
+        keyword: Token,
+        method: Token,
+        slot: Option<Slot>,
+    },
+    This {
+        keyword: Token,
+        slot: Option<Slot>,
+    },
     Unary {
         operator: Token,
         right: Box<Expr>,
     },
     Variable {
         name: Token,
+        slot: Option<Slot>,
     },
 }
+
+impl Expr {
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Expr::Assign { name, value, .. } => name.span.start..value.span().end,
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                left.span().start..right.span().end
+            }
+            Expr::Call { callee, paren, .. } => callee.span().start..paren.span.end,
+            Expr::Get { object, name } => object.span().start..name.span.end,
+            Expr::Function { span, .. }
+            | Expr::Grouping { span, .. }
+            | Expr::Interpolation { span, .. }
+            | Expr::Literal { span, .. }
+            | Expr::List { span, .. }
+            | Expr::Map { span, .. } => span.clone(),
+            Expr::Index {
+                object, bracket, ..
+            } => object.span().start..bracket.span.end,
+            Expr::Set { object, value, .. } | Expr::SetIndex { object, value, .. } => {
+                object.span().start..value.span().end
+            }
+            Expr::Super {
+                keyword, method, ..
+            } => keyword.span.start..method.span.end,
+            Expr::This { keyword, .. } => keyword.span.clone(),
+            Expr::Unary { operator, right } => operator.span.start..right.span().end,
+            Expr::Variable { name, .. } => name.span.clone(),
+        }
+    }
+}
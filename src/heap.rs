@@ -0,0 +1,116 @@
+use std::{cell::Cell, rc::Rc};
+
+use anyhow::{bail, Result};
+
+/// Counts the bytes held by the strings, lists, maps, instances, functions and local variables a
+/// script creates, and enforces an optional cap on them.
+#[derive(Debug, Default)]
+pub struct Heap {
+    current: Cell<usize>,
+    peak: Cell<usize>,
+    limit: Cell<Option<usize>>,
+}
+
+/// Memory use reported by [`Interpreter::memory_stats`](crate::Interpreter::memory_stats).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Bytes held by values that are still alive.
+    pub current: usize,
+    /// The most bytes held at any one time.
+    pub peak: usize,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit.get()
+    }
+
+    pub fn set_limit(&self, limit: Option<usize>) {
+        self.limit.set(limit);
+    }
+
+    pub fn stats(&self) -> MemoryStats {
+        MemoryStats {
+            current: self.current.get(),
+            peak: self.peak.get(),
+        }
+    }
+
+    fn charge(&self, bytes: usize) -> Result<()> {
+        let current = self.current.get() + bytes;
+        if self.limit.get().is_some_and(|limit| current > limit) {
+            bail!("Out of memory.");
+        }
+        self.current.set(current);
+        self.peak.set(self.peak.get().max(current));
+        Ok(())
+    }
+
+    fn release(&self, bytes: usize) {
+        self.current.set(self.current.get() - bytes);
+    }
+}
+
+/// The bytes one value holds on a [`Heap`], given back when the value is dropped. Values made
+/// outside a running script, such as string literals and values built by the host, aren't
+/// charged to any heap.
+#[derive(Debug, Default)]
+pub struct Allocation {
+    heap: Option<Rc<Heap>>,
+    bytes: Cell<usize>,
+}
+
+impl Allocation {
+    /// Charges `bytes` to `heap`, failing with an "Out of memory." error if that would take it
+    /// over its limit.
+    pub fn new(heap: Option<&Rc<Heap>>, bytes: usize) -> Result<Self> {
+        if let Some(heap) = heap {
+            heap.charge(bytes)?;
+        }
+        Ok(Self {
+            heap: heap.cloned(),
+            bytes: Cell::new(bytes),
+        })
+    }
+
+    /// Starts tracking a value that holds nothing yet, such as an empty map, on `heap`.
+    pub fn empty(heap: Option<&Rc<Heap>>) -> Self {
+        Self {
+            heap: heap.cloned(),
+            bytes: Cell::new(0),
+        }
+    }
+
+    /// The heap this value is charged to. Values derived from it, like a slice of a list, are
+    /// charged to the same heap.
+    pub fn heap(&self) -> Option<&Rc<Heap>> {
+        self.heap.as_ref()
+    }
+
+    pub fn grow(&self, bytes: usize) -> Result<()> {
+        if let Some(heap) = &self.heap {
+            heap.charge(bytes)?;
+            self.bytes.set(self.bytes.get() + bytes);
+        }
+        Ok(())
+    }
+
+    pub fn shrink(&self, bytes: usize) {
+        if let Some(heap) = &self.heap {
+            heap.release(bytes);
+            self.bytes.set(self.bytes.get() - bytes);
+        }
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        if let Some(heap) = &self.heap {
+            heap.release(self.bytes.get());
+        }
+    }
+}
@@ -1,137 +1,667 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    ops::Range,
+    rc::Rc,
+    time::Instant,
+};
+
 use anyhow::{bail, Result};
 use thiserror::Error;
 
 use crate::{
-    built_in::register_builtins, environment::Environment, expr::Expr, lox_callable::LoxCallable,
-    stmt::Stmt, token::Value, token_type::TokenType,
+    built_in::register_builtins,
+    environment::{Environment, Globals},
+    expr::{Expr, Slot},
+    heap::{Heap, MemoryStats},
+    lox_callable::{Arity, BuiltInFunction, CallContext, LoxCallable, LoxFunction},
+    lox_class::LoxClass,
+    lox_instance::LoxInstance,
+    lox_list::LoxList,
+    lox_map::LoxMap,
+    lox_string::LoxString,
+    stack::StackLimit,
+    stmt::Stmt,
+    token::{Token, Value},
+    token_type::TokenType,
 };
 
+#[derive(Error, Debug, Clone)]
+pub enum RuntimeError {
+    #[error("{message}\n[line {line}]")]
+    Error {
+        line: usize,
+        span: Range<usize>,
+        message: String,
+        trace: Vec<TraceFrame>,
+    },
+    /// A value raised by `throw`. Errors have to be `Send`, so the interpreter keeps the value
+    /// itself until a `catch` claims it; `message` describes it in case nothing does.
+    #[error("Uncaught exception: {message}\n[line {line}]")]
+    Thrown {
+        line: usize,
+        span: Range<usize>,
+        message: String,
+        trace: Vec<TraceFrame>,
+    },
+}
+
+/// Raised when a script uses up the execution budget or runs past the deadline set on its
+/// [`Interpreter`]. Unlike a [`RuntimeError`], scripts can't catch it.
+#[derive(Error, Debug, Clone, Copy, PartialEq)]
+pub enum ResourceExhausted {
+    #[error("Execution budget exhausted.")]
+    Fuel,
+    #[error("Execution deadline exceeded.")]
+    Deadline,
+}
+
+/// Raised when statements or expressions nest so deeply that the native stack is about to run
+/// out. The call whose body ran out fails with a "Stack overflow." runtime error, as if it had
+/// gone past the call depth limit.
 #[derive(Error, Debug)]
-pub enum ReturnError {
-    #[error("return value")]
-    ReturnValue { value: Value },
+#[error("Stack overflow.")]
+struct NativeStackExhausted;
+
+/// The deadline is only checked once per this many statements, as reading the clock costs far
+/// more than executing a typical statement.
+const DEADLINE_CHECK_INTERVAL: u32 = 1024;
+
+/// One entry of a stack trace: a function that was running and the line it had reached. The
+/// outermost frame of a trace is always `<script>`, the top-level code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceFrame {
+    pub function: String,
+    pub line: usize,
+}
+
+/// A call to a Lox function that hasn't returned yet.
+struct CallFrame {
+    /// Shared with the function, or `None` for an anonymous one, so that making a call doesn't
+    /// allocate.
+    function: Option<Rc<str>>,
+    /// Line of the call expression in the caller.
+    call_line: usize,
+}
+
+impl RuntimeError {
+    pub fn new(token: &Token, message: &str) -> Self {
+        Self::with_span(token, token.span.clone(), message)
+    }
+
+    pub fn with_span(token: &Token, span: Range<usize>, message: &str) -> Self {
+        Self::Error {
+            line: token.line,
+            span,
+            message: message.to_string(),
+            trace: vec![],
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        match self {
+            Self::Error { line, .. } | Self::Thrown { line, .. } => *line,
+        }
+    }
+
+    pub fn span(&self) -> &Range<usize> {
+        match self {
+            Self::Error { span, .. } | Self::Thrown { span, .. } => span,
+        }
+    }
+
+    /// The Lox call stack at the point the error was raised, innermost call first.
+    pub fn trace(&self) -> &[TraceFrame] {
+        match self {
+            Self::Error { trace, .. } | Self::Thrown { trace, .. } => trace,
+        }
+    }
+
+    fn trace_mut(&mut self) -> &mut Vec<TraceFrame> {
+        match self {
+            Self::Error { trace, .. } | Self::Thrown { trace, .. } => trace,
+        }
+    }
+}
+
+/// How a statement finished executing. `break`, `continue` and `return` unwind to the enclosing
+/// loop or call as values rather than as errors.
+pub enum ControlFlow {
+    Normal,
+    Break,
+    Continue,
+    Return(Value),
 }
 
 pub struct Interpreter {
-    globals: Environment,
+    globals: Globals,
+    /// Class of the values runtime errors turn into when caught.
+    error_class: Rc<LoxClass>,
+    /// The value most recently raised by `throw`, waiting to be claimed by a `catch`.
+    thrown: RefCell<Option<Value>>,
+    call_stack: RefCell<Vec<CallFrame>>,
+    max_call_depth: usize,
+    /// Statements left to execute, or `None` for no limit.
+    fuel: Cell<Option<u64>>,
+    deadline: Option<Instant>,
+    /// Statements executed since the deadline was last checked.
+    steps: Cell<u32>,
+    heap: Rc<Heap>,
+    /// Where statements and expressions stop nesting, found by `interpret` on the thread it
+    /// runs on.
+    stack_limit: Cell<StackLimit>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
+    /// The default limit on nested Lox calls, shared with the bytecode VM. Each call nests several
+    /// native stack frames, so this many need more stack than a spawned thread gets by default;
+    /// [`run_with_call_depth`](crate::run_with_call_depth) provides a thread big enough for any
+    /// limit.
+    pub const DEFAULT_MAX_CALL_DEPTH: usize = 1024;
+
     pub fn new() -> Self {
-        let mut environment = Environment::new(None);
-        register_builtins(&mut environment);
+        let mut interpreter = Self {
+            globals: Globals::default(),
+            error_class: Rc::new(LoxClass::new("Error".to_string(), None, HashMap::new())),
+            thrown: RefCell::new(None),
+            call_stack: RefCell::new(vec![]),
+            max_call_depth: Self::DEFAULT_MAX_CALL_DEPTH,
+            fuel: Cell::new(None),
+            deadline: None,
+            steps: Cell::new(0),
+            heap: Rc::new(Heap::new()),
+            stack_limit: Cell::new(StackLimit::default()),
+        };
+        register_builtins(&mut interpreter);
+        interpreter
+    }
 
-        Self {
-            globals: environment,
-        }
+    /// Registers a host function as a global. The function can capture state from the host;
+    /// any error it returns becomes a Lox runtime error at the call site, unless it came from
+    /// calling back into Lox.
+    pub fn define_native<F>(&mut self, name: &str, arity: impl Into<Arity>, func: F)
+    where
+        F: Fn(&mut CallContext, &[Value]) -> Result<Value> + 'static,
+    {
+        let function = BuiltInFunction {
+            name: name.to_string(),
+            arity: arity.into(),
+            func: Rc::new(func),
+        };
+        self.globals.define(
+            name.to_string(),
+            Value::Callable(LoxCallable::BuiltIn(function)),
+        );
+    }
+
+    pub fn max_call_depth(&self) -> usize {
+        self.max_call_depth
+    }
+
+    /// Sets how many Lox calls may be active at once. A call beyond the limit raises a
+    /// "Stack overflow." runtime error instead of overflowing the native stack, provided the
+    /// thread's stack is large enough for the limit.
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.max_call_depth = max_call_depth;
+    }
+
+    /// The number of statements the interpreter may still execute, or `None` if unlimited.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel.get()
+    }
+
+    /// Limits how many more statements the interpreter may execute before failing with
+    /// [`ResourceExhausted::Fuel`]. `None` removes the limit. Globals survive running out, so
+    /// topping the fuel up lets a later call to [`Interpreter::interpret`] carry on.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel.set(fuel);
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Makes execution fail with [`ResourceExhausted::Deadline`] once `deadline` has passed.
+    /// `None` removes the deadline.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+        self.steps.set(0);
+    }
+
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.heap.limit()
+    }
+
+    /// Caps the bytes that strings, lists, maps, instances, functions and local variables
+    /// created by scripts may hold at once. Going over raises a catchable "Out of memory." runtime error. `None` removes the
+    /// cap.
+    pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+        self.heap.set_limit(limit);
+    }
+
+    /// Current and peak memory held by values scripts have created.
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.heap.stats()
     }
 
-    pub fn get_globals(&self) -> Environment {
+    pub(crate) fn heap(&self) -> &Rc<Heap> {
+        &self.heap
+    }
+
+    pub fn get_globals(&self) -> Globals {
         self.globals.clone()
     }
 
-    pub fn interpret(&self, statements: &[Stmt]) -> Result<()> {
-        let environment = self.get_globals();
+    /// Creates the value a `catch` clause receives for a runtime error: an `Error` instance with
+    /// `message` and `line` fields. It isn't charged to the heap, so that running out of memory
+    /// can still be caught.
+    pub fn error_value(&self, message: Value, line: usize) -> Value {
+        let instance = LoxInstance::new(self.error_class.clone());
+        let fields = [("message", message), ("line", Value::Number(line as f64))];
+        for (name, value) in fields {
+            instance
+                .set_field(name, value)
+                .expect("Untracked instances have no memory limit.");
+        }
+        Value::Instance(instance)
+    }
+
+    /// Turns an error unwinding through a `try` statement into the value its `catch` clause
+    /// receives, or hands the error back if it can't be caught.
+    fn catch(&self, error: anyhow::Error) -> Result<Value> {
+        match error.downcast::<RuntimeError>() {
+            Ok(RuntimeError::Error { line, message, .. }) => {
+                Ok(self.error_value(Value::String(message.into()), line))
+            }
+            Ok(RuntimeError::Thrown { .. }) => Ok(self
+                .thrown
+                .borrow_mut()
+                .take()
+                .expect("Thrown errors leave their value with the interpreter.")),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Describes a thrown value for when no `catch` claims it.
+    fn describe_thrown(&self, value: &Value) -> String {
+        if let Value::Instance(instance) = value {
+            if Rc::ptr_eq(&instance.class, &self.error_class) {
+                if let Some(message) = instance.get_field("message") {
+                    return message.to_string();
+                }
+            }
+        }
+        value.to_string()
+    }
+
+    /// Executes `statements` in the global environment and returns the value of the final
+    /// statement when it is an expression statement, or `nil` otherwise.
+    pub fn interpret(&self, statements: &[Stmt]) -> Result<Value> {
+        self.stack_limit.set(StackLimit::current());
+        let mut result = Value::None;
         for statement in statements {
-            self.visit_statement(statement, environment.clone())?;
+            result = match statement {
+                Stmt::Expression { expression } => self.visit_expression(expression, None),
+                _ => self.visit_statement(statement, None).map(|_| Value::None),
+            }
+            .map_err(|error| self.record_trace(error))?;
         }
-        Ok(())
+        Ok(result)
+    }
+
+    /// Runs `call` with a frame for `function`, called at `paren`, on the call stack. `function`
+    /// is `None` for anonymous functions.
+    pub(crate) fn with_frame<T>(
+        &self,
+        function: Option<&Rc<str>>,
+        paren: &Token,
+        call: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        if self.call_stack.borrow().len() >= self.max_call_depth {
+            bail!(RuntimeError::new(paren, "Stack overflow."));
+        }
+
+        self.call_stack.borrow_mut().push(CallFrame {
+            function: function.cloned(),
+            call_line: paren.line,
+        });
+        let result = call().map_err(|error| self.record_trace(error));
+        self.call_stack.borrow_mut().pop();
+        result.map_err(|error| {
+            if error.is::<NativeStackExhausted>() {
+                RuntimeError::new(paren, "Stack overflow.").into()
+            } else {
+                error
+            }
+        })
+    }
+
+    /// The current Lox call stack, innermost call first, with the innermost function at `line`.
+    pub fn stack_trace(&self, line: usize) -> Vec<TraceFrame> {
+        let mut trace = vec![];
+        let mut line = line;
+        for frame in self.call_stack.borrow().iter().rev() {
+            trace.push(TraceFrame {
+                function: frame
+                    .function
+                    .as_deref()
+                    .unwrap_or("<anonymous>")
+                    .to_string(),
+                line,
+            });
+            line = frame.call_line;
+        }
+        trace.push(TraceFrame {
+            function: "<script>".to_string(),
+            line,
+        });
+        trace
+    }
+
+    /// Captures the call stack into a runtime error the first time it unwinds out of a frame,
+    /// while the frames it was raised in are still on the stack.
+    fn record_trace(&self, mut error: anyhow::Error) -> anyhow::Error {
+        if let Some(error) = error.downcast_mut::<RuntimeError>() {
+            if error.trace().is_empty() {
+                *error.trace_mut() = self.stack_trace(error.line());
+            }
+        }
+        error
     }
 
-    pub fn execute_block(&self, statements: &Vec<Stmt>, environment: Environment) -> Result<Value> {
+    pub fn execute_block(
+        &self,
+        statements: &[Stmt],
+        environment: &Environment,
+    ) -> Result<ControlFlow> {
         for statement in statements {
-            let result = self.visit_statement(statement, environment.clone());
-            if let Err(err) = result {
-                match err.downcast_ref::<ReturnError>() {
-                    Some(ReturnError::ReturnValue { value }) => return Ok(value.clone()),
-                    None => (),
+            match self.visit_statement(statement, Some(environment))? {
+                ControlFlow::Normal => (),
+                flow => return Ok(flow),
+            }
+        }
+        Ok(ControlFlow::Normal)
+    }
+
+    /// Defines a variable in the innermost local scope, or as a global at the top level. Local
+    /// variables are charged to the heap.
+    fn define(&self, name: &Token, value: Value, environment: Option<&Environment>) -> Result<()> {
+        match environment {
+            Some(environment) => environment
+                .define(value)
+                .map_err(|error| RuntimeError::new(name, &error.to_string()).into()),
+            None => {
+                self.globals.define(name.lexeme.clone(), value);
+                Ok(())
+            }
+        }
+    }
+
+    /// A new scope nested in `environment`, with its variables charged to the heap.
+    fn new_scope(&self, environment: Option<&Environment>) -> Environment {
+        Environment::new(environment.cloned(), Some(&self.heap))
+    }
+
+    /// Charges one statement against the fuel and deadline.
+    fn consume_fuel(&self) -> Result<()> {
+        if let Some(fuel) = self.fuel.get() {
+            if fuel == 0 {
+                bail!(ResourceExhausted::Fuel);
+            }
+            self.fuel.set(Some(fuel - 1));
+        }
+
+        if let Some(deadline) = self.deadline {
+            let steps = self.steps.get() + 1;
+            if steps < DEADLINE_CHECK_INTERVAL {
+                self.steps.set(steps);
+            } else {
+                self.steps.set(0);
+                if Instant::now() >= deadline {
+                    bail!(ResourceExhausted::Deadline);
                 }
-                return Err(err);
             }
         }
-        Ok(Value::None)
+        Ok(())
+    }
+
+    /// Fails once the native stack reaches the limit `interpret` set.
+    #[inline]
+    fn check_stack(&self) -> Result<()> {
+        if self.stack_limit.get().exceeded() {
+            bail!(NativeStackExhausted);
+        }
+        Ok(())
     }
 
-    fn visit_statement(&self, statement: &Stmt, environment: Environment) -> Result<()> {
+    fn visit_statement(
+        &self,
+        statement: &Stmt,
+        environment: Option<&Environment>,
+    ) -> Result<ControlFlow> {
+        self.consume_fuel()?;
+        self.check_stack()?;
         match statement {
             Stmt::Expression { expression } => {
                 self.visit_expression(expression, environment)?;
-                Ok(())
+                Ok(ControlFlow::Normal)
             }
             Stmt::Print { expression } => {
                 let value = self.visit_expression(expression, environment)?;
                 println!("{}", value);
-                Ok(())
+                Ok(ControlFlow::Normal)
             }
             Stmt::Var { name, initializer } => {
                 let mut value = Value::None;
                 if let Some(init) = initializer {
-                    value = self.visit_expression(init, environment.clone())?;
+                    value = self.visit_expression(init, environment)?;
                 }
-                environment.define(name.lexeme.clone(), value);
-                Ok(())
+                self.define(name, value, environment)?;
+                Ok(ControlFlow::Normal)
             }
             Stmt::Block { statements } => {
-                let new_environment = Environment::new(Some(environment));
-                self.execute_block(statements, new_environment)?;
-                Ok(())
+                self.execute_block(statements, &self.new_scope(environment))
             }
             Stmt::If {
                 condition,
                 then_branch,
                 else_branch,
             } => {
-                if self
-                    .visit_expression(condition, environment.clone())?
-                    .is_truthy()
-                {
-                    self.visit_statement(then_branch, environment)?;
+                if self.visit_expression(condition, environment)?.is_truthy() {
+                    self.visit_statement(then_branch, environment)
                 } else if let Some(else_branch) = else_branch {
-                    self.visit_statement(else_branch, environment)?;
+                    self.visit_statement(else_branch, environment)
+                } else {
+                    Ok(ControlFlow::Normal)
                 }
-                Ok(())
             }
-            Stmt::While { condition, body } => {
-                while self
-                    .visit_expression(condition, environment.clone())?
-                    .is_truthy()
-                {
-                    self.visit_statement(body, environment.clone())?;
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => {
+                while self.visit_expression(condition, environment)?.is_truthy() {
+                    match self.visit_statement(body, environment)? {
+                        ControlFlow::Normal | ControlFlow::Continue => (),
+                        ControlFlow::Break => break,
+                        flow @ ControlFlow::Return(_) => return Ok(flow),
+                    }
+                    if let Some(increment) = increment {
+                        self.visit_expression(increment, environment)?;
+                    }
                 }
-                Ok(())
+                Ok(ControlFlow::Normal)
             }
+            Stmt::Throw { keyword, value } => {
+                let span = keyword.span.start..value.span().end;
+                let value = self.visit_expression(value, environment)?;
+                let message = self.describe_thrown(&value);
+                *self.thrown.borrow_mut() = Some(value);
+                bail!(RuntimeError::Thrown {
+                    line: keyword.line,
+                    span,
+                    message,
+                    trace: vec![],
+                })
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                let mut result = self.execute_block(body, &self.new_scope(environment));
+                // Errors scripts can't catch, like running out of fuel, skip finally blocks too.
+                if matches!(&result, Err(error) if !error.is::<RuntimeError>()) {
+                    return result;
+                }
+
+                if let Some(catch) = catch {
+                    result = match result {
+                        Err(error) => {
+                            let value = self.catch(error)?;
+                            // Like the error value itself, the scope holding it isn't charged, so
+                            // that running out of memory can always be caught.
+                            let catch_environment = Environment::new(environment.cloned(), None);
+                            catch_environment
+                                .define(value)
+                                .expect("Untracked scopes have no memory limit.");
+                            match self.execute_block(&catch.body, &catch_environment) {
+                                Err(error) if !error.is::<RuntimeError>() => return Err(error),
+                                result => result,
+                            }
+                        }
+                        result => result,
+                    };
+                }
+
+                if let Some(finally) = finally {
+                    // A value thrown past this statement has to survive any exceptions the
+                    // finally block throws and catches itself.
+                    let pending = self.thrown.take();
+                    match self.execute_block(finally, &self.new_scope(environment))? {
+                        ControlFlow::Normal => *self.thrown.borrow_mut() = pending,
+                        flow => return Ok(flow),
+                    }
+                }
+                result
+            }
+            Stmt::Break { .. } => Ok(ControlFlow::Break),
+            Stmt::Continue { .. } => Ok(ControlFlow::Continue),
             Stmt::Function { name, params, body } => {
-                let function = LoxCallable::LoxFunction(
-                    Box::new(Stmt::Function {
-                        name: name.clone(),
-                        params: params.clone(),
-                        body: body.clone(),
-                    }),
-                    environment.clone(),
-                );
-                environment.define(name.lexeme.clone(), Value::Callable(function));
-                Ok(())
+                let function = LoxFunction::new(
+                    Some(name.lexeme.as_str().into()),
+                    params,
+                    body,
+                    environment.cloned(),
+                    false,
+                    Some(&self.heap),
+                )
+                .map_err(|error| RuntimeError::new(name, &error.to_string()))?;
+                let function = LoxCallable::LoxFunction(Rc::new(function));
+                self.define(name, Value::Callable(function), environment)?;
+                Ok(ControlFlow::Normal)
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                let superclass = match superclass {
+                    Some(superclass) => {
+                        if let Expr::Variable {
+                            name: super_name, ..
+                        } = superclass
+                        {
+                            if super_name.lexeme == name.lexeme {
+                                bail!(RuntimeError::new(
+                                    super_name,
+                                    "A class can't inherit from itself."
+                                ));
+                            }
+                        }
+
+                        match self.visit_expression(superclass, environment)? {
+                            Value::Callable(LoxCallable::Class(class)) => Some(class),
+                            _ => bail!(RuntimeError::with_span(
+                                name,
+                                superclass.span(),
+                                "Superclass must be a class."
+                            )),
+                        }
+                    }
+                    None => None,
+                };
+
+                let method_environment = match &superclass {
+                    Some(superclass) => {
+                        let environment = self.new_scope(environment);
+                        let superclass = Value::Callable(LoxCallable::Class(superclass.clone()));
+                        environment
+                            .define(superclass)
+                            .map_err(|error| RuntimeError::new(name, &error.to_string()))?;
+                        Some(environment)
+                    }
+                    None => environment.cloned(),
+                };
+
+                let mut class_methods = HashMap::new();
+                for method in methods {
+                    if let Stmt::Function {
+                        name: method_name,
+                        params,
+                        body,
+                    } = method
+                    {
+                        let function = LoxFunction::new(
+                            Some(method_name.lexeme.as_str().into()),
+                            params,
+                            body,
+                            method_environment.clone(),
+                            method_name.lexeme == "init",
+                            Some(&self.heap),
+                        )
+                        .map_err(|error| RuntimeError::new(method_name, &error.to_string()))?;
+                        class_methods.insert(method_name.lexeme.clone(), function);
+                    }
+                }
+
+                let class = LoxClass::new(name.lexeme.clone(), superclass, class_methods);
+                self.define(
+                    name,
+                    Value::Callable(LoxCallable::Class(Rc::new(class))),
+                    environment,
+                )?;
+                Ok(ControlFlow::Normal)
             }
-            Stmt::Return { keyword, value } => {
+            Stmt::Return { value, .. } => {
                 let mut return_value = Value::None;
                 if let Some(value) = value {
                     return_value = self.visit_expression(value, environment)?;
                 }
-                bail!(ReturnError::ReturnValue {
-                    value: return_value
-                })
+                Ok(ControlFlow::Return(return_value))
             }
         }
     }
 
-    fn visit_expression(&self, expression: &Expr, environment: Environment) -> Result<Value> {
+    fn visit_expression(
+        &self,
+        expression: &Expr,
+        environment: Option<&Environment>,
+    ) -> Result<Value> {
+        self.check_stack()?;
         match expression {
             Expr::Binary {
                 left,
                 operator,
                 right,
             } => {
-                let left = self.visit_expression(left, environment.clone())?;
+                let left = self.visit_expression(left, environment)?;
                 let right = self.visit_expression(right, environment)?;
 
                 match operator.token_type {
@@ -141,60 +671,100 @@ impl Interpreter {
                         }
 
                         if let (Value::String(l), Value::String(r)) = (left, right) {
-                            return Ok(Value::String(format!("{}{}", l, r)));
+                            let string =
+                                LoxString::concat(Some(&self.heap), &[l, r]).map_err(|error| {
+                                    RuntimeError::with_span(
+                                        operator,
+                                        expression.span(),
+                                        &error.to_string(),
+                                    )
+                                })?;
+                            return Ok(Value::String(string));
                         }
-                        panic!("Invalid syntax");
+                        bail!(RuntimeError::with_span(
+                            operator,
+                            expression.span(),
+                            "Operands must be two numbers or two strings."
+                        ));
                     }
                     TokenType::Minus => {
                         if let (Value::Number(l), Value::Number(r)) = (left, right) {
                             return Ok(Value::Number(l - r));
                         }
-                        panic!("Invalid syntax");
+                        bail!(RuntimeError::with_span(
+                            operator,
+                            expression.span(),
+                            "Operands must be numbers."
+                        ));
                     }
                     TokenType::Slash => {
                         if let (Value::Number(l), Value::Number(r)) = (left, right) {
                             return Ok(Value::Number(l / r));
                         }
-                        panic!("Invalid syntax");
+                        bail!(RuntimeError::with_span(
+                            operator,
+                            expression.span(),
+                            "Operands must be numbers."
+                        ));
                     }
                     TokenType::Star => {
                         if let (Value::Number(l), Value::Number(r)) = (left, right) {
                             return Ok(Value::Number(l * r));
                         }
-                        panic!("Invalid syntax");
+                        bail!(RuntimeError::with_span(
+                            operator,
+                            expression.span(),
+                            "Operands must be numbers."
+                        ));
                     }
 
                     TokenType::Greater => {
                         if let (Value::Number(l), Value::Number(r)) = (left, right) {
                             return Ok(Value::from_bool(l > r));
                         }
-                        panic!("Invalid syntax");
+                        bail!(RuntimeError::with_span(
+                            operator,
+                            expression.span(),
+                            "Operands must be numbers."
+                        ));
                     }
                     TokenType::GreaterEqual => {
                         if let (Value::Number(l), Value::Number(r)) = (left, right) {
                             return Ok(Value::from_bool(l >= r));
                         }
-                        panic!("Invalid syntax");
+                        bail!(RuntimeError::with_span(
+                            operator,
+                            expression.span(),
+                            "Operands must be numbers."
+                        ));
                     }
                     TokenType::Less => {
                         if let (Value::Number(l), Value::Number(r)) = (left, right) {
                             return Ok(Value::from_bool(l < r));
                         }
-                        panic!("Invalid syntax");
+                        bail!(RuntimeError::with_span(
+                            operator,
+                            expression.span(),
+                            "Operands must be numbers."
+                        ));
                     }
                     TokenType::LessEqual => {
                         if let (Value::Number(l), Value::Number(r)) = (left, right) {
                             return Ok(Value::from_bool(l <= r));
                         }
-                        panic!("Invalid syntax");
+                        bail!(RuntimeError::with_span(
+                            operator,
+                            expression.span(),
+                            "Operands must be numbers."
+                        ));
                     }
                     TokenType::BangEqual => Ok(Value::from_bool(!left.is_equal(&right))),
                     TokenType::EqualEqual => Ok(Value::from_bool(left.is_equal(&right))),
-                    _ => panic!("Invalid syntax"),
+                    _ => bail!(RuntimeError::new(operator, "Unknown operator.")),
                 }
             }
-            Expr::Grouping { expression } => self.visit_expression(expression, environment),
-            Expr::Literal { value } => Ok(value.clone()),
+            Expr::Grouping { expression, .. } => self.visit_expression(expression, environment),
+            Expr::Literal { value, .. } => Ok(value.clone()),
             Expr::Unary { operator, right } => {
                 let right = self.visit_expression(right, environment)?;
 
@@ -204,15 +774,22 @@ impl Interpreter {
                         if let Value::Number(value) = right {
                             return Ok(Value::Number(-value));
                         }
-                        panic!("Invalid syntax");
+                        bail!(RuntimeError::with_span(
+                            operator,
+                            expression.span(),
+                            "Operand must be a number."
+                        ));
                     }
-                    _ => panic!("Invalid syntax"),
+                    _ => bail!(RuntimeError::new(operator, "Unknown operator.")),
                 }
             }
-            Expr::Variable { name } => Ok(environment.get(name)),
-            Expr::Assign { name, value } => {
-                let value = self.visit_expression(value, environment.clone())?;
-                environment.assign(name, value.clone());
+            Expr::Variable { name, slot } => self.look_up_variable(name, *slot, environment),
+            Expr::Assign { name, value, slot } => {
+                let value = self.visit_expression(value, environment)?;
+                match (slot, environment) {
+                    (Some(slot), Some(environment)) => environment.assign_at(*slot, value.clone()),
+                    _ => self.globals.assign(name, value.clone())?,
+                }
                 Ok(value)
             }
             Expr::Logical {
@@ -220,7 +797,7 @@ impl Interpreter {
                 operator,
                 right,
             } => {
-                let left = self.visit_expression(left, environment.clone())?;
+                let left = self.visit_expression(left, environment)?;
 
                 if operator.token_type == TokenType::Or {
                     if left.is_truthy() {
@@ -232,24 +809,227 @@ impl Interpreter {
 
                 self.visit_expression(right, environment)
             }
+            Expr::Get { object, name } => {
+                match self.visit_expression(object, environment)? {
+                    Value::Instance(instance) => return instance.get(name),
+                    Value::List(list) => return list.get_method(name),
+                    Value::Map(map) => return map.get_method(name),
+                    _ => (),
+                }
+
+                bail!(RuntimeError::with_span(
+                    name,
+                    expression.span(),
+                    "Only instances have properties."
+                ))
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => {
+                let object = self.visit_expression(object, environment)?;
+
+                if let Value::Instance(instance) = object {
+                    let value = self.visit_expression(value, environment)?;
+                    instance.set(name, value.clone()).map_err(|error| {
+                        RuntimeError::with_span(name, expression.span(), &error.to_string())
+                    })?;
+                    return Ok(value);
+                }
+
+                bail!(RuntimeError::with_span(
+                    name,
+                    expression.span(),
+                    "Only instances have fields."
+                ))
+            }
+            Expr::Interpolation { start, parts, .. } => {
+                let values = parts
+                    .iter()
+                    .map(|part| self.visit_expression(part, environment))
+                    .collect::<Result<Vec<_>>>()?;
+                let string = LoxString::concat(Some(&self.heap), &values).map_err(|error| {
+                    RuntimeError::with_span(start, expression.span(), &error.to_string())
+                })?;
+                Ok(Value::String(string))
+            }
+            Expr::List {
+                bracket, elements, ..
+            } => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.visit_expression(element, environment))
+                    .collect::<Result<Vec<_>>>()?;
+                let list = LoxList::with_heap(Some(&self.heap), elements).map_err(|error| {
+                    RuntimeError::with_span(bracket, expression.span(), &error.to_string())
+                })?;
+                Ok(Value::List(list))
+            }
+            Expr::Function {
+                keyword,
+                params,
+                body,
+                ..
+            } => {
+                let function = LoxFunction::new(
+                    None,
+                    params,
+                    body,
+                    environment.cloned(),
+                    false,
+                    Some(&self.heap),
+                )
+                .map_err(|error| {
+                    RuntimeError::with_span(keyword, expression.span(), &error.to_string())
+                })?;
+                Ok(Value::Callable(LoxCallable::LoxFunction(Rc::new(function))))
+            }
+            Expr::Map { brace, entries, .. } => {
+                let map = LoxMap::with_heap(Some(&self.heap));
+                for (key, value) in entries {
+                    let key_value = self.visit_expression(key, environment)?;
+                    let value = self.visit_expression(value, environment)?;
+                    map.set(key_value, value).map_err(|error| {
+                        RuntimeError::with_span(brace, key.span(), &error.to_string())
+                    })?;
+                }
+                Ok(Value::Map(map))
+            }
+            Expr::Index {
+                object,
+                bracket,
+                index,
+            } => {
+                let object = self.visit_expression(object, environment)?;
+                let index = self.visit_expression(index, environment)?;
+
+                let element = match object {
+                    Value::List(list) => list.get(&index),
+                    Value::Map(map) => map.get(&index),
+                    _ => bail!(RuntimeError::with_span(
+                        bracket,
+                        expression.span(),
+                        "Only lists and maps can be indexed."
+                    )),
+                };
+                element.map_err(|error| {
+                    RuntimeError::with_span(bracket, expression.span(), &error.to_string()).into()
+                })
+            }
+            Expr::SetIndex {
+                object,
+                bracket,
+                index,
+                value,
+            } => {
+                let object = self.visit_expression(object, environment)?;
+                let index = self.visit_expression(index, environment)?;
+
+                if !matches!(object, Value::List(_) | Value::Map(_)) {
+                    bail!(RuntimeError::with_span(
+                        bracket,
+                        expression.span(),
+                        "Only lists and maps can be indexed."
+                    ));
+                }
+
+                let value = self.visit_expression(value, environment)?;
+                let result = match object {
+                    Value::List(list) => list.set(&index, value.clone()),
+                    Value::Map(map) => map.set(index, value.clone()),
+                    _ => unreachable!(),
+                };
+                result.map_err(|error| {
+                    RuntimeError::with_span(bracket, expression.span(), &error.to_string())
+                })?;
+                Ok(value)
+            }
+            Expr::Super { method, slot, .. } => {
+                let (slot, environment) = slot
+                    .zip(environment)
+                    .expect("'super' is always resolved to a local scope.");
+                let superclass = environment.get_at(slot);
+                // "this" is the only variable in the scope just inside the one holding "super".
+                let object = environment.get_at(Slot {
+                    depth: slot.depth - 1,
+                    index: 0,
+                });
+
+                if let (
+                    Value::Callable(LoxCallable::Class(superclass)),
+                    Value::Instance(instance),
+                ) = (superclass, object)
+                {
+                    if let Some(function) = superclass.find_method(&method.lexeme) {
+                        let function = function
+                            .bind(&instance)
+                            .map_err(|error| RuntimeError::new(method, &error.to_string()))?;
+                        return Ok(Value::Callable(LoxCallable::LoxFunction(Rc::new(function))));
+                    }
+                }
+
+                bail!(RuntimeError::new(
+                    method,
+                    &format!("Undefined property '{}'.", method.lexeme)
+                ))
+            }
+            Expr::This { keyword, slot } => self.look_up_variable(keyword, *slot, environment),
             Expr::Call {
                 callee,
                 paren,
                 arguments,
             } => {
-                let callee = self.visit_expression(callee, environment.clone())?;
+                let callee = self.visit_expression(callee, environment)?;
 
                 let argument_list: Result<Vec<Value>> = arguments
                     .iter()
-                    .map(|a| self.visit_expression(a, environment.clone()))
+                    .map(|a| self.visit_expression(a, environment))
                     .collect();
 
-                if let Value::Callable(callable) = callee {
-                    return callable.call(self, &argument_list?);
-                }
+                self.call_value(callee, paren, expression.span(), &argument_list?)
+            }
+        }
+    }
 
-                panic!("Syntax error");
+    pub(crate) fn call_value(
+        &self,
+        callee: Value,
+        paren: &Token,
+        span: Range<usize>,
+        arguments: &[Value],
+    ) -> Result<Value> {
+        if let Value::Callable(callable) = callee {
+            if !callable.arity().accepts(arguments.len()) {
+                bail!(RuntimeError::with_span(
+                    paren,
+                    span,
+                    &format!(
+                        "Expected {} arguments but got {}.",
+                        callable.arity(),
+                        arguments.len()
+                    )
+                ));
             }
+            return callable.call(self, paren, arguments);
+        }
+
+        bail!(RuntimeError::with_span(
+            paren,
+            span,
+            "Can only call functions and classes."
+        ))
+    }
+
+    fn look_up_variable(
+        &self,
+        name: &Token,
+        slot: Option<Slot>,
+        environment: Option<&Environment>,
+    ) -> Result<Value> {
+        match (slot, environment) {
+            (Some(slot), Some(environment)) => Ok(environment.get_at(slot)),
+            _ => self.globals.get(name),
         }
     }
 }
@@ -0,0 +1,152 @@
+use thiserror::Error;
+
+pub mod ast_printer;
+pub mod built_in;
+pub mod diagnostic;
+pub mod environment;
+pub mod expr;
+pub mod heap;
+pub mod interpreter;
+pub mod lox_callable;
+pub mod lox_class;
+pub mod lox_instance;
+pub mod lox_list;
+pub mod lox_map;
+pub mod lox_string;
+pub mod parser;
+pub mod resolver;
+pub mod scanner;
+pub mod stack;
+pub mod stmt;
+pub mod token;
+pub mod token_type;
+pub mod vm;
+
+/// Re-exported so hosts can return errors from native functions without depending on it directly.
+pub use anyhow;
+pub use heap::MemoryStats;
+pub use interpreter::{Interpreter, ResourceExhausted, RuntimeError, TraceFrame};
+pub use lox_callable::{Arity, CallContext};
+pub use lox_string::LoxString;
+pub use parser::{ParseError, Parser};
+pub use resolver::{ResolveError, Resolver};
+pub use scanner::{ScanError, Scanner};
+pub use token::Value;
+
+/// Native stack reserved per level of Lox recursion by [`run_with_call_depth`]. A call takes
+/// several times more in debug builds, which don't merge the stack slots of locals. Stack that
+/// is never touched costs only address space.
+const STACK_PER_CALL: usize = if cfg!(debug_assertions) {
+    256 * 1024
+} else {
+    64 * 1024
+};
+
+/// Runs `f` on a new thread with a native stack large enough for the tree-walking interpreter to
+/// nest `max_call_depth` Lox calls, and waits for its result. Sessions can't move between
+/// threads, so `f` should create its own and set its limit to match with
+/// [`Interpreter::set_max_call_depth`]. Fails if the operating system can't provide a stack of
+/// that size.
+pub fn run_with_call_depth<T, F>(max_call_depth: usize, f: F) -> std::io::Result<T>
+where
+    T: Send,
+    F: FnOnce() -> T + Send,
+{
+    let stack_size = (max_call_depth + 16).saturating_mul(STACK_PER_CALL);
+    std::thread::scope(|scope| {
+        let thread = std::thread::Builder::new()
+            .stack_size(stack_size)
+            .spawn_scoped(scope, f)?;
+        match thread.join() {
+            Ok(result) => Ok(result),
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    })
+}
+
+/// Runs `source` in a fresh [`Lox`] session and returns the value of its final statement if
+/// that statement is an expression, or `nil` otherwise.
+pub fn run_source(source: &str) -> Result<Value, LoxError> {
+    Lox::new().eval(source)
+}
+
+#[derive(Error, Debug)]
+pub enum LoxError {
+    #[error("{}", join_lines(.0))]
+    Scan(Vec<ScanError>),
+    #[error("{}", join_lines(.0))]
+    Parse(Vec<ParseError>),
+    #[error("{}", join_lines(.0))]
+    Resolve(Vec<ResolveError>),
+    #[error(transparent)]
+    Runtime(RuntimeError),
+    #[error(transparent)]
+    ResourceExhausted(ResourceExhausted),
+    /// Any other error that stopped the script, such as one raised by host code in a way the
+    /// interpreter doesn't recognise.
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+fn join_lines<T: ToString>(errors: &[T]) -> String {
+    errors
+        .iter()
+        .map(|error| error.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// An embeddable Lox session. Definitions made by one call to [`Lox::eval`] stay visible to
+/// every later call on the same instance.
+pub struct Lox {
+    interpreter: Interpreter,
+}
+
+impl Lox {
+    pub fn new() -> Self {
+        Self {
+            interpreter: Interpreter::new(),
+        }
+    }
+
+    pub fn interpreter(&self) -> &Interpreter {
+        &self.interpreter
+    }
+
+    pub fn interpreter_mut(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+
+    /// Runs `source` and returns the value of its final statement if that statement is an
+    /// expression, or `nil` otherwise.
+    pub fn eval(&mut self, source: &str) -> Result<Value, LoxError> {
+        let (tokens, errors) = Scanner::new(source).scan_tokens();
+        if !errors.is_empty() {
+            return Err(LoxError::Scan(errors));
+        }
+        let mut parser = Parser::new(tokens);
+        let mut statements = parser.parse().map_err(LoxError::Parse)?;
+
+        let mut resolver = Resolver::new();
+        resolver
+            .resolve(&mut statements)
+            .map_err(LoxError::Resolve)?;
+
+        self.interpreter.interpret(&statements).map_err(|error| {
+            let error = match error.downcast::<RuntimeError>() {
+                Ok(error) => return LoxError::Runtime(error),
+                Err(error) => error,
+            };
+            match error.downcast::<ResourceExhausted>() {
+                Ok(error) => LoxError::ResourceExhausted(error),
+                Err(error) => LoxError::Other(error),
+            }
+        })
+    }
+}
+
+impl Default for Lox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,51 +1,271 @@
-use crate::{environment::Environment, interpreter::Interpreter, stmt::Stmt, token::Value};
+use crate::{
+    environment::Environment,
+    expr::Slot,
+    heap::{Allocation, Heap},
+    interpreter::{ControlFlow, Interpreter, ResourceExhausted, RuntimeError, TraceFrame},
+    lox_class::LoxClass,
+    lox_instance::LoxInstance,
+    stmt::Stmt,
+    token::{Token, Value},
+};
 use anyhow::Result;
-use std::fmt::Debug;
+use std::{
+    fmt::{Debug, Display},
+    rc::Rc,
+};
 
 #[derive(Clone)]
 pub enum LoxCallable {
     BuiltIn(BuiltInFunction),
-    LoxFunction(Box<Stmt>, Environment),
+    LoxFunction(Rc<LoxFunction>),
+    Class(Rc<LoxClass>),
 }
 
+/// Signature of a host function callable from Lox. Any error it returns is reported as a
+/// runtime error at the call site, except errors passed on from [`CallContext::call`], which
+/// keep their kind.
+pub type NativeFn = dyn Fn(&mut CallContext, &[Value]) -> Result<Value>;
+
 #[derive(Clone)]
 pub struct BuiltInFunction {
     pub name: String,
+    pub arity: Arity,
+    pub func: Rc<NativeFn>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match self {
+            Arity::Exactly(arity) => count == *arity,
+            Arity::AtLeast(arity) => count >= *arity,
+        }
+    }
+}
+
+impl From<usize> for Arity {
+    fn from(arity: usize) -> Self {
+        Arity::Exactly(arity)
+    }
+}
+
+impl Display for Arity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Arity::Exactly(arity) => write!(f, "{}", arity),
+            Arity::AtLeast(arity) => write!(f, "at least {}", arity),
+        }
+    }
+}
+
+/// State handed to a native function for the duration of a single call.
+pub struct CallContext<'a> {
+    interpreter: &'a Interpreter,
+    paren: &'a Token,
+}
+
+impl<'a> CallContext<'a> {
+    pub fn interpreter(&self) -> &Interpreter {
+        self.interpreter
+    }
+
+    /// Line of the call expression that invoked the native function.
+    pub fn line(&self) -> usize {
+        self.paren.line
+    }
+
+    /// The Lox functions active at this call, innermost first, ending with `<script>`.
+    pub fn stack_trace(&self) -> Vec<TraceFrame> {
+        self.interpreter.stack_trace(self.paren.line)
+    }
+
+    /// Calls a Lox function, class or native passed in as a value, e.g. a callback argument.
+    pub fn call(&mut self, callee: &Value, arguments: &[Value]) -> Result<Value> {
+        self.interpreter.call_value(
+            callee.clone(),
+            self.paren,
+            self.paren.span.clone(),
+            arguments,
+        )
+    }
+}
+
+/// Bytes charged to the heap for each function value. The body isn't included, as every value
+/// made from a declaration shares it.
+const FUNCTION_SIZE: usize = std::mem::size_of::<LoxFunction>();
+
+pub struct LoxFunction {
+    /// `None` for anonymous functions.
+    pub name: Option<Rc<str>>,
     pub arity: usize,
-    pub func: fn(&Interpreter, &[Value]) -> Value,
+    pub body: Rc<[Stmt]>,
+    /// Scope the function was declared in, or `None` for functions declared at the top level.
+    pub closure: Option<Environment>,
+    pub is_initializer: bool,
+    allocation: Allocation,
+}
+
+impl LoxFunction {
+    /// Creates a function, charged to `heap`, that shares `body` with its declaration.
+    pub fn new(
+        name: Option<Rc<str>>,
+        params: &[Token],
+        body: &Rc<[Stmt]>,
+        closure: Option<Environment>,
+        is_initializer: bool,
+        heap: Option<&Rc<Heap>>,
+    ) -> Result<Self> {
+        Ok(Self {
+            name,
+            arity: params.len(),
+            body: body.clone(),
+            closure,
+            is_initializer,
+            allocation: Allocation::new(heap, FUNCTION_SIZE)?,
+        })
+    }
+
+    pub fn bind(&self, instance: &LoxInstance) -> Result<LoxFunction> {
+        let heap = self.allocation.heap();
+        let environment = Environment::new(self.closure.clone(), heap);
+        environment.define(Value::Instance(instance.clone()))?;
+        Ok(LoxFunction {
+            name: self.name.clone(),
+            arity: self.arity,
+            body: self.body.clone(),
+            closure: Some(environment),
+            is_initializer: self.is_initializer,
+            allocation: Allocation::new(heap, FUNCTION_SIZE)?,
+        })
+    }
+
+    fn call(&self, interpreter: &Interpreter, paren: &Token, arguments: &[Value]) -> Result<Value> {
+        let environment = Environment::new(self.closure.clone(), self.allocation.heap());
+        for argument in arguments {
+            environment
+                .define(argument.clone())
+                .map_err(|error| RuntimeError::new(paren, &error.to_string()))?;
+        }
+
+        let result = interpreter.execute_block(&self.body, &environment)?;
+        if self.is_initializer {
+            let closure = self
+                .closure
+                .as_ref()
+                .expect("Initializers are always bound.");
+            return Ok(closure.get_at(Slot { depth: 0, index: 0 }));
+        }
+        match result {
+            ControlFlow::Return(value) => Ok(value),
+            // The resolver rejects `break` and `continue` that would leave a function.
+            ControlFlow::Normal | ControlFlow::Break | ControlFlow::Continue => Ok(Value::None),
+        }
+    }
+
+    /// Moves the values held by the scope this function closes over into `pending`, if only
+    /// this function holds on to it. See [`Value::take_contents`].
+    pub(crate) fn take_contents(&mut self, pending: &mut Vec<Value>) {
+        if let Some(mut closure) = self.closure.take() {
+            closure.take_contents(pending);
+        }
+    }
 }
 
 impl LoxCallable {
-    pub fn call(self, interpreter: &Interpreter, arguments: &[Value]) -> Result<Value> {
+    pub fn arity(&self) -> Arity {
+        match self {
+            LoxCallable::BuiltIn(callable) => callable.arity,
+            LoxCallable::LoxFunction(function) => Arity::Exactly(function.arity),
+            LoxCallable::Class(class) => Arity::Exactly(
+                class
+                    .find_method("init")
+                    .map_or(0, |initializer| initializer.arity),
+            ),
+        }
+    }
+
+    pub fn call(
+        self,
+        interpreter: &Interpreter,
+        paren: &Token,
+        arguments: &[Value],
+    ) -> Result<Value> {
         match self {
-            LoxCallable::BuiltIn(callable) => Ok((callable.func)(interpreter, arguments)),
-            LoxCallable::LoxFunction(declaration, closure) => match *declaration {
-                Stmt::Function { name, params, body } => {
-                    let environment = Environment::new(Some(closure));
-                    for (i, argument) in params.iter().enumerate() {
-                        environment.define(argument.lexeme.clone(), arguments[i].clone());
+            LoxCallable::BuiltIn(callable) => {
+                let mut context = CallContext { interpreter, paren };
+                (callable.func)(&mut context, arguments).map_err(|error| {
+                    // Errors from calling back into Lox through the context keep their kind, so
+                    // scripts still can't catch running out of fuel inside a callback.
+                    if error.is::<RuntimeError>() || error.is::<ResourceExhausted>() {
+                        error
+                    } else {
+                        RuntimeError::new(paren, &error.to_string()).into()
                     }
+                })
+            }
+            LoxCallable::LoxFunction(function) => {
+                interpreter.with_frame(function.name.as_ref(), paren, || {
+                    function.call(interpreter, paren, arguments)
+                })
+            }
+            LoxCallable::Class(class) => {
+                let instance = LoxInstance::with_heap(class.clone(), Some(interpreter.heap()));
+                if let Some(initializer) = class.find_method("init") {
+                    interpreter.with_frame(initializer.name.as_ref(), paren, || {
+                        initializer
+                            .bind(&instance)
+                            .map_err(|error| RuntimeError::new(paren, &error.to_string()))?
+                            .call(interpreter, paren, arguments)
+                    })?;
+                }
+                Ok(Value::Instance(instance))
+            }
+        }
+    }
 
-                    let result = interpreter.execute_block(&body, environment)?;
-                    Ok(result)
+    /// Moves the values held by a function's closure or a class's methods into `pending`, if
+    /// nothing else shares the function or class. See [`Value::take_contents`].
+    pub(crate) fn take_contents(&mut self, pending: &mut Vec<Value>) {
+        match self {
+            LoxCallable::BuiltIn(_) => (),
+            LoxCallable::LoxFunction(function) => {
+                if let Some(function) = Rc::get_mut(function) {
+                    function.take_contents(pending);
+                }
+            }
+            LoxCallable::Class(class) => {
+                if let Some(class) = Rc::get_mut(class) {
+                    class.take_contents(pending);
                 }
-                _ => panic!("Syntax error"),
-            },
+            }
         }
     }
 }
 
+/// Callables are equal only to themselves. Each evaluation of a function declaration or method
+/// access makes a new function.
 impl PartialEq for LoxCallable {
     fn eq(&self, other: &Self) -> bool {
-        false
+        match (self, other) {
+            (Self::BuiltIn(left), Self::BuiltIn(right)) => Rc::ptr_eq(&left.func, &right.func),
+            (Self::LoxFunction(left), Self::LoxFunction(right)) => Rc::ptr_eq(left, right),
+            (Self::Class(left), Self::Class(right)) => Rc::ptr_eq(left, right),
+            _ => false,
+        }
     }
 }
 
 impl Debug for LoxCallable {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::BuiltIn(arg0) => f.debug_tuple("BuiltIn").finish(),
-            Self::LoxFunction(arg0, arg1) => f.debug_tuple("LoxFunction").finish(),
+            Self::BuiltIn(callable) => f.debug_tuple("BuiltIn").field(&callable.name).finish(),
+            Self::LoxFunction(_) => f.debug_tuple("LoxFunction").finish(),
+            Self::Class(class) => f.debug_tuple("Class").field(&class.name).finish(),
         }
     }
 }
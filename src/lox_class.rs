@@ -0,0 +1,63 @@
+use std::{collections::HashMap, fmt::Debug, rc::Rc};
+
+use crate::{
+    lox_callable::{LoxCallable, LoxFunction},
+    token::Value,
+};
+
+pub struct LoxClass {
+    pub name: String,
+    superclass: Option<Rc<LoxClass>>,
+    methods: HashMap<String, LoxFunction>,
+}
+
+impl LoxClass {
+    pub fn new(
+        name: String,
+        superclass: Option<Rc<LoxClass>>,
+        methods: HashMap<String, LoxFunction>,
+    ) -> Self {
+        Self {
+            name,
+            superclass,
+            methods,
+        }
+    }
+
+    pub fn find_method(&self, name: &str) -> Option<&LoxFunction> {
+        if let Some(method) = self.methods.get(name) {
+            Some(method)
+        } else if let Some(superclass) = &self.superclass {
+            superclass.find_method(name)
+        } else {
+            None
+        }
+    }
+
+    /// Moves the values the methods' closures hold into `pending`, and the superclass too. See
+    /// [`Value::take_contents`].
+    pub(crate) fn take_contents(&mut self, pending: &mut Vec<Value>) {
+        for (_, mut method) in self.methods.drain() {
+            method.take_contents(pending);
+        }
+        if let Some(superclass) = self.superclass.take() {
+            pending.push(Value::Callable(LoxCallable::Class(superclass)));
+        }
+    }
+}
+
+impl Drop for LoxClass {
+    fn drop(&mut self) {
+        let mut pending = vec![];
+        self.take_contents(&mut pending);
+        Value::drop_all(pending);
+    }
+}
+
+impl Debug for LoxClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoxClass")
+            .field("name", &self.name)
+            .finish()
+    }
+}
@@ -0,0 +1,98 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    heap::{Allocation, Heap},
+    interpreter::RuntimeError,
+    lox_callable::LoxCallable,
+    lox_class::LoxClass,
+    token::{Token, Value},
+};
+
+#[derive(Clone, Debug)]
+pub struct LoxInstance {
+    pub class: Rc<LoxClass>,
+    fields: Rc<RefCell<HashMap<String, Value>>>,
+    allocation: Rc<Allocation>,
+}
+
+impl LoxInstance {
+    pub fn new(class: Rc<LoxClass>) -> Self {
+        Self::with_heap(class, None)
+    }
+
+    /// Creates an instance whose fields will be charged to `heap`.
+    pub fn with_heap(class: Rc<LoxClass>, heap: Option<&Rc<Heap>>) -> Self {
+        Self {
+            class,
+            fields: Rc::new(RefCell::new(HashMap::new())),
+            allocation: Rc::new(Allocation::empty(heap)),
+        }
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Value> {
+        if let Some(value) = self.fields.borrow().get(&name.lexeme) {
+            return Ok(value.clone());
+        }
+
+        if let Some(method) = self.class.find_method(&name.lexeme) {
+            let method = method
+                .bind(self)
+                .map_err(|error| RuntimeError::new(name, &error.to_string()))?;
+            return Ok(Value::Callable(LoxCallable::LoxFunction(Rc::new(method))));
+        }
+
+        bail!(RuntimeError::new(
+            name,
+            &format!("Undefined property '{}'.", name.lexeme)
+        ))
+    }
+
+    pub fn set(&self, name: &Token, value: Value) -> Result<()> {
+        self.set_field(&name.lexeme, value)
+    }
+
+    pub fn get_field(&self, name: &str) -> Option<Value> {
+        self.fields.borrow().get(name).cloned()
+    }
+
+    pub fn set_field(&self, name: &str, value: Value) -> Result<()> {
+        let mut fields = self.fields.borrow_mut();
+        match fields.get_mut(name) {
+            Some(field) => *field = value,
+            None => {
+                self.allocation
+                    .grow(name.len() + std::mem::size_of::<(String, Value)>())?;
+                fields.insert(name.to_string(), value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the fields into `pending` if no other instance shares them, along with the class
+    /// if nothing else refers to it. See [`Value::take_contents`].
+    pub(crate) fn take_contents(&mut self, pending: &mut Vec<Value>) {
+        if let Some(fields) = Rc::get_mut(&mut self.fields) {
+            let fields = fields.get_mut().drain().map(|(_, value)| value);
+            pending.extend(fields.filter(Value::holds_values));
+        }
+        if let Some(class) = Rc::get_mut(&mut self.class) {
+            class.take_contents(pending);
+        }
+    }
+}
+
+impl Drop for LoxInstance {
+    fn drop(&mut self) {
+        let mut pending = vec![];
+        self.take_contents(&mut pending);
+        Value::drop_all(pending);
+    }
+}
+
+impl PartialEq for LoxInstance {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.fields, &other.fields)
+    }
+}
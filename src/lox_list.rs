@@ -0,0 +1,211 @@
+use std::{
+    cell::RefCell,
+    fmt::{Debug, Display},
+    rc::Rc,
+};
+
+use anyhow::{anyhow, bail, Result};
+
+use crate::{
+    heap::{Allocation, Heap},
+    interpreter::RuntimeError,
+    lox_callable::{Arity, BuiltInFunction, LoxCallable, NativeFn},
+    token::{Token, Value},
+};
+
+/// Bytes charged to the heap for each element.
+const ELEMENT_SIZE: usize = std::mem::size_of::<Value>();
+
+/// A growable list. Cloning a `LoxList` shares the underlying elements, so every alias sees
+/// updates made through any of them.
+#[derive(Clone)]
+pub struct LoxList {
+    elements: Rc<RefCell<Vec<Value>>>,
+    allocation: Rc<Allocation>,
+}
+
+impl LoxList {
+    pub fn new(elements: Vec<Value>) -> Self {
+        Self {
+            elements: Rc::new(RefCell::new(elements)),
+            allocation: Rc::default(),
+        }
+    }
+
+    /// Creates a list whose elements are charged to `heap`, or to no heap if it is `None`.
+    pub fn with_heap(heap: Option<&Rc<Heap>>, elements: Vec<Value>) -> Result<Self> {
+        let allocation = Allocation::new(heap, elements.len() * ELEMENT_SIZE)?;
+        Ok(Self {
+            elements: Rc::new(RefCell::new(elements)),
+            allocation: Rc::new(allocation),
+        })
+    }
+
+    pub fn heap(&self) -> Option<&Rc<Heap>> {
+        self.allocation.heap()
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.borrow().is_empty()
+    }
+
+    pub fn get(&self, index: &Value) -> Result<Value> {
+        let index = self.position(index, false)?;
+        Ok(self.elements.borrow()[index].clone())
+    }
+
+    pub fn set(&self, index: &Value, value: Value) -> Result<()> {
+        let index = self.position(index, false)?;
+        self.elements.borrow_mut()[index] = value;
+        Ok(())
+    }
+
+    /// Looks up a built-in method and binds it to this list.
+    pub fn get_method(&self, name: &Token) -> Result<Value> {
+        let list = self.clone();
+        let (arity, func): (usize, Rc<NativeFn>) = match name.lexeme.as_str() {
+            "len" => (0, Rc::new(move |_, _| Ok(Value::Number(list.len() as f64)))),
+            "push" => (
+                1,
+                Rc::new(move |_, arguments| {
+                    list.allocation.grow(ELEMENT_SIZE)?;
+                    list.elements.borrow_mut().push(arguments[0].clone());
+                    Ok(Value::None)
+                }),
+            ),
+            "pop" => (
+                0,
+                Rc::new(move |_, _| {
+                    let element = list
+                        .elements
+                        .borrow_mut()
+                        .pop()
+                        .ok_or_else(|| anyhow!("Can't pop from an empty list."))?;
+                    list.allocation.shrink(ELEMENT_SIZE);
+                    Ok(element)
+                }),
+            ),
+            "insert" => (
+                2,
+                Rc::new(move |_, arguments| {
+                    let index = list.position(&arguments[0], true)?;
+                    list.allocation.grow(ELEMENT_SIZE)?;
+                    list.elements
+                        .borrow_mut()
+                        .insert(index, arguments[1].clone());
+                    Ok(Value::None)
+                }),
+            ),
+            "remove" => (
+                1,
+                Rc::new(move |_, arguments| {
+                    let index = list.position(&arguments[0], false)?;
+                    list.allocation.shrink(ELEMENT_SIZE);
+                    Ok(list.elements.borrow_mut().remove(index))
+                }),
+            ),
+            "slice" => (
+                2,
+                Rc::new(move |_, arguments| {
+                    let start = list.position(&arguments[0], true)?;
+                    let end = list.position(&arguments[1], true)?;
+                    if start > end {
+                        bail!("Slice start can't be after its end.");
+                    }
+                    let elements = list.elements.borrow()[start..end].to_vec();
+                    Ok(Value::List(LoxList::with_heap(list.heap(), elements)?))
+                }),
+            ),
+            _ => bail!(RuntimeError::new(
+                name,
+                &format!("Undefined property '{}'.", name.lexeme)
+            )),
+        };
+
+        Ok(Value::Callable(LoxCallable::BuiltIn(BuiltInFunction {
+            name: name.lexeme.clone(),
+            arity: Arity::Exactly(arity),
+            func,
+        })))
+    }
+
+    /// Converts a Lox index into a position in the list. `allow_end` permits the position one
+    /// past the last element, as used when inserting or slicing.
+    fn position(&self, index: &Value, allow_end: bool) -> Result<usize> {
+        let index = match index {
+            Value::Number(index) if index.fract() == 0.0 => *index,
+            _ => bail!("List index must be an integer."),
+        };
+
+        let len = self.len();
+        let limit = if allow_end { len + 1 } else { len };
+        if index < 0.0 || index >= limit as f64 {
+            bail!("Index {} out of range for list of length {}.", index, len);
+        }
+        Ok(index as usize)
+    }
+
+    /// Moves the elements into `pending` if no other list shares them. See
+    /// [`Value::take_contents`].
+    pub(crate) fn take_contents(&mut self, pending: &mut Vec<Value>) {
+        if let Some(elements) = Rc::get_mut(&mut self.elements) {
+            pending.extend(elements.get_mut().drain(..).filter(Value::holds_values));
+        }
+    }
+}
+
+impl Drop for LoxList {
+    fn drop(&mut self) {
+        if let Some(elements) = Rc::get_mut(&mut self.elements) {
+            Value::drop_all(std::mem::take(elements.get_mut()));
+        }
+    }
+}
+
+impl PartialEq for LoxList {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.elements, &other.elements)
+    }
+}
+
+impl Debug for LoxList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoxList").field("len", &self.len()).finish()
+    }
+}
+
+thread_local! {
+    /// Lists currently being displayed, so a list that contains itself prints as `[...]`
+    /// instead of recursing forever.
+    static DISPLAYING: RefCell<Vec<*const RefCell<Vec<Value>>>> = const { RefCell::new(vec![]) };
+}
+
+impl Display for LoxList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pointer = Rc::as_ptr(&self.elements);
+        if DISPLAYING.with(|displaying| displaying.borrow().contains(&pointer)) {
+            return f.write_str("[...]");
+        }
+
+        DISPLAYING.with(|displaying| displaying.borrow_mut().push(pointer));
+        let result = (|| {
+            f.write_str("[")?;
+            for (i, element) in self.elements.borrow().iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                match element {
+                    Value::String(string) => write!(f, "\"{}\"", string)?,
+                    _ => write!(f, "{}", element)?,
+                }
+            }
+            f.write_str("]")
+        })();
+        DISPLAYING.with(|displaying| displaying.borrow_mut().pop());
+        result
+    }
+}
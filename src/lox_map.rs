@@ -0,0 +1,257 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{Debug, Display},
+    rc::Rc,
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    heap::{Allocation, Heap},
+    interpreter::RuntimeError,
+    lox_callable::{Arity, BuiltInFunction, LoxCallable, NativeFn},
+    lox_list::LoxList,
+    token::{Token, Value},
+};
+
+/// A hashable form of the values allowed as map keys. Two keys are the same exactly when the
+/// values they came from are equal under [`Value::is_equal`]. NaN is not equal to itself, so it
+/// is rejected rather than stored as a key that could never be found again.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum MapKey {
+    Nil,
+    Bool(bool),
+    Number(u64),
+    String(String),
+}
+
+impl MapKey {
+    fn from_value(value: &Value) -> Result<Self> {
+        Ok(match value {
+            Value::None => MapKey::Nil,
+            Value::True => MapKey::Bool(true),
+            Value::False => MapKey::Bool(false),
+            // -0 and 0 compare equal, so they must hash the same.
+            Value::Number(n) if *n == 0.0 => MapKey::Number(0.0f64.to_bits()),
+            Value::Number(n) if n.is_nan() => bail!("Map keys cannot be NaN."),
+            Value::Number(n) => MapKey::Number(n.to_bits()),
+            Value::String(s) => MapKey::String(s.to_string()),
+            _ => bail!("Map keys must be strings, numbers, booleans or nil."),
+        })
+    }
+
+    /// Bytes charged to the heap for an entry with this key, including the copy of a string
+    /// key's text the key holds.
+    fn entry_size(&self) -> usize {
+        let text = match self {
+            MapKey::String(string) => string.len(),
+            _ => 0,
+        };
+        std::mem::size_of::<(Value, Value)>() + std::mem::size_of::<(MapKey, usize)>() + text
+    }
+}
+
+#[derive(Default)]
+struct Entries {
+    positions: HashMap<MapKey, usize>,
+    entries: Vec<(Value, Value)>,
+}
+
+/// A hash map that remembers insertion order. Like lists, cloning a `LoxMap` shares the
+/// underlying entries.
+#[derive(Clone, Default)]
+pub struct LoxMap {
+    entries: Rc<RefCell<Entries>>,
+    allocation: Rc<Allocation>,
+}
+
+impl LoxMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty map whose entries will be charged to `heap`.
+    pub fn with_heap(heap: Option<&Rc<Heap>>) -> Self {
+        Self {
+            entries: Rc::default(),
+            allocation: Rc::new(Allocation::empty(heap)),
+        }
+    }
+
+    pub fn heap(&self) -> Option<&Rc<Heap>> {
+        self.allocation.heap()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.borrow().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.borrow().entries.is_empty()
+    }
+
+    pub fn has(&self, key: &Value) -> Result<bool> {
+        let key = MapKey::from_value(key)?;
+        Ok(self.entries.borrow().positions.contains_key(&key))
+    }
+
+    pub fn get(&self, key: &Value) -> Result<Value> {
+        let entries = self.entries.borrow();
+        match entries.positions.get(&MapKey::from_value(key)?) {
+            Some(&position) => Ok(entries.entries[position].1.clone()),
+            None => bail!("Undefined key {}.", quoted(key)),
+        }
+    }
+
+    /// Inserts or updates an entry. Updating keeps the key's original position.
+    pub fn set(&self, key: Value, value: Value) -> Result<()> {
+        let map_key = MapKey::from_value(&key)?;
+        let mut entries = self.entries.borrow_mut();
+        match entries.positions.get(&map_key) {
+            Some(&position) => entries.entries[position].1 = value,
+            None => {
+                self.allocation.grow(map_key.entry_size())?;
+                let position = entries.entries.len();
+                entries.positions.insert(map_key, position);
+                entries.entries.push((key, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes an entry, returning whether the key was present.
+    pub fn delete(&self, key: &Value) -> Result<bool> {
+        let mut entries = self.entries.borrow_mut();
+        let map_key = MapKey::from_value(key)?;
+        let position = match entries.positions.remove(&map_key) {
+            Some(position) => position,
+            None => return Ok(false),
+        };
+        self.allocation.shrink(map_key.entry_size());
+
+        entries.entries.remove(position);
+        for later in entries.positions.values_mut() {
+            if *later > position {
+                *later -= 1;
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn keys(&self) -> Vec<Value> {
+        let entries = self.entries.borrow();
+        entries.entries.iter().map(|(key, _)| key.clone()).collect()
+    }
+
+    pub fn values(&self) -> Vec<Value> {
+        let entries = self.entries.borrow();
+        entries
+            .entries
+            .iter()
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    /// Looks up a built-in method and binds it to this map.
+    pub fn get_method(&self, name: &Token) -> Result<Value> {
+        let map = self.clone();
+        let (arity, func): (usize, Rc<NativeFn>) = match name.lexeme.as_str() {
+            "len" => (0, Rc::new(move |_, _| Ok(Value::Number(map.len() as f64)))),
+            "keys" => (
+                0,
+                Rc::new(move |_, _| Ok(Value::List(LoxList::with_heap(map.heap(), map.keys())?))),
+            ),
+            "values" => (
+                0,
+                Rc::new(move |_, _| Ok(Value::List(LoxList::with_heap(map.heap(), map.values())?))),
+            ),
+            "has" => (
+                1,
+                Rc::new(move |_, arguments| Ok(Value::from_bool(map.has(&arguments[0])?))),
+            ),
+            "delete" => (
+                1,
+                Rc::new(move |_, arguments| Ok(Value::from_bool(map.delete(&arguments[0])?))),
+            ),
+            _ => bail!(RuntimeError::new(
+                name,
+                &format!("Undefined property '{}'.", name.lexeme)
+            )),
+        };
+
+        Ok(Value::Callable(LoxCallable::BuiltIn(BuiltInFunction {
+            name: name.lexeme.clone(),
+            arity: Arity::Exactly(arity),
+            func,
+        })))
+    }
+
+    /// Moves the keys and values into `pending` if no other map shares them. See
+    /// [`Value::take_contents`].
+    pub(crate) fn take_contents(&mut self, pending: &mut Vec<Value>) {
+        if let Some(entries) = Rc::get_mut(&mut self.entries) {
+            let entries = entries.get_mut();
+            entries.positions.clear();
+            // Keys are never collections, so only the values can hold more values.
+            let values = entries.entries.drain(..).map(|(_, value)| value);
+            pending.extend(values.filter(Value::holds_values));
+        }
+    }
+}
+
+impl Drop for LoxMap {
+    fn drop(&mut self) {
+        let mut pending = vec![];
+        self.take_contents(&mut pending);
+        Value::drop_all(pending);
+    }
+}
+
+/// Formats a value the way it appears inside a collection, with strings in quotes.
+fn quoted(value: &Value) -> String {
+    match value {
+        Value::String(string) => format!("\"{}\"", string),
+        _ => value.to_string(),
+    }
+}
+
+impl PartialEq for LoxMap {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.entries, &other.entries)
+    }
+}
+
+impl Debug for LoxMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoxMap").field("len", &self.len()).finish()
+    }
+}
+
+thread_local! {
+    /// Maps currently being displayed, so a map that contains itself prints as `{...}`.
+    static DISPLAYING: RefCell<Vec<*const RefCell<Entries>>> = const { RefCell::new(vec![]) };
+}
+
+impl Display for LoxMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pointer = Rc::as_ptr(&self.entries);
+        if DISPLAYING.with(|displaying| displaying.borrow().contains(&pointer)) {
+            return f.write_str("{...}");
+        }
+
+        DISPLAYING.with(|displaying| displaying.borrow_mut().push(pointer));
+        let result = (|| {
+            f.write_str("{")?;
+            for (i, (key, value)) in self.entries.borrow().entries.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}: {}", quoted(key), quoted(value))?;
+            }
+            f.write_str("}")
+        })();
+        DISPLAYING.with(|displaying| displaying.borrow_mut().pop());
+        result
+    }
+}
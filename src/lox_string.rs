@@ -0,0 +1,124 @@
+use std::{
+    fmt::{Debug, Display, Write},
+    ops::Deref,
+    rc::Rc,
+};
+
+use anyhow::Result;
+
+use crate::heap::{Allocation, Heap};
+
+/// An immutable string. Cloning a `LoxString` shares the text rather than copying it, so only
+/// creating a new string counts against a script's memory limit.
+#[derive(Clone)]
+pub struct LoxString {
+    data: Rc<StringData>,
+}
+
+struct StringData {
+    text: String,
+    _allocation: Allocation,
+}
+
+impl LoxString {
+    /// Creates a string charged to `heap`, or to no heap if it is `None`.
+    pub fn with_heap(heap: Option<&Rc<Heap>>, text: String) -> Result<Self> {
+        let allocation = Allocation::new(heap, text.len())?;
+        Ok(Self {
+            data: Rc::new(StringData {
+                text,
+                _allocation: allocation,
+            }),
+        })
+    }
+
+    /// Creates a string by writing out `parts` one after another, the way `print` shows them.
+    /// Each piece is charged to `heap` before it is copied in, so a string too big for the
+    /// limit fails without ever being built.
+    pub fn concat<T: Display>(heap: Option<&Rc<Heap>>, parts: &[T]) -> Result<Self> {
+        let mut writer = ChargedWriter {
+            text: String::new(),
+            allocation: Allocation::empty(heap),
+            error: None,
+        };
+        for part in parts {
+            if write!(writer, "{}", part).is_err() {
+                return Err(writer
+                    .error
+                    .expect("Only running out of memory stops a string being built."));
+            }
+        }
+        Ok(Self {
+            data: Rc::new(StringData {
+                text: writer.text,
+                _allocation: writer.allocation,
+            }),
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.data.text
+    }
+}
+
+/// Builds up a string, charging for each piece before adding it.
+struct ChargedWriter {
+    text: String,
+    allocation: Allocation,
+    /// Why the last write failed.
+    error: Option<anyhow::Error>,
+}
+
+impl Write for ChargedWriter {
+    fn write_str(&mut self, piece: &str) -> std::fmt::Result {
+        if let Err(error) = self.allocation.grow(piece.len()) {
+            self.error = Some(error);
+            return Err(std::fmt::Error);
+        }
+        self.text.push_str(piece);
+        Ok(())
+    }
+}
+
+impl From<String> for LoxString {
+    fn from(text: String) -> Self {
+        Self {
+            data: Rc::new(StringData {
+                text,
+                _allocation: Allocation::default(),
+            }),
+        }
+    }
+}
+
+impl From<&str> for LoxString {
+    fn from(text: &str) -> Self {
+        text.to_string().into()
+    }
+}
+
+impl Deref for LoxString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq for LoxString {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Debug for LoxString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Display for LoxString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -1,62 +1,278 @@
-use std::{fs, io};
-
-use ast_printer::AstPrinter;
-use parser::Parser;
-use scanner::Scanner;
-
-use crate::interpreter::Interpreter;
-
-mod ast_printer;
-mod built_in;
-mod environment;
-mod expr;
-mod interpreter;
-mod lox_callable;
-mod parser;
-mod scanner;
-mod stmt;
-mod token;
-mod token_type;
+use std::{fs, path::PathBuf};
+
+use lox::{
+    diagnostic, token::Token, token_type::TokenType, vm::Vm, Interpreter, Lox, LoxError, Parser,
+    Scanner,
+};
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+// Exit codes from sysexits.h, as used by the reference jlox.
+const EX_USAGE: i32 = 64;
+const EX_DATAERR: i32 = 65;
+const EX_NOINPUT: i32 = 66;
+const EX_SOFTWARE: i32 = 70;
+const EX_OSERR: i32 = 71;
+
+#[derive(Clone, Copy)]
+enum Backend {
+    TreeWalker,
+    Vm,
+}
+
+#[derive(Clone, Copy)]
+struct Options {
+    backend: Backend,
+    /// Overrides the limit on nested calls.
+    max_call_depth: Option<usize>,
+}
 
 fn main() {
-    let mut args = std::env::args();
-    if args.len() > 2 {
-        eprintln!("Usage: lox [script]");
-        std::process::exit(64);
-    } else if args.len() == 2 {
-        run_file(&args.nth(1).unwrap());
-    } else {
-        run_prompt();
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let backend = match args.iter().position(|arg| arg == "--vm") {
+        Some(index) => {
+            args.remove(index);
+            Backend::Vm
+        }
+        None => Backend::TreeWalker,
+    };
+
+    let max_call_depth = match args.iter().position(|arg| arg == "--max-depth") {
+        Some(index) => match args.get(index + 1).and_then(|depth| depth.parse().ok()) {
+            Some(depth) => {
+                args.drain(index..=index + 1);
+                Some(depth)
+            }
+            None => usage(),
+        },
+        None => None,
+    };
+
+    if args.len() > 1 {
+        usage();
+    }
+
+    let options = Options {
+        backend,
+        max_call_depth,
+    };
+    let start = move || match args.first() {
+        Some(path) => run_file(path, options),
+        None => run_prompt(options),
+    };
+    // The tree-walker recurses on the native stack, so it runs on a thread with room for its
+    // whole call depth limit. The bytecode VM keeps its frames on the heap but is run the same
+    // way.
+    let depth = max_call_depth.unwrap_or(Interpreter::DEFAULT_MAX_CALL_DEPTH);
+    if let Err(error) = lox::run_with_call_depth(depth, start) {
+        eprintln!("Could not start the interpreter: {}", error);
+        std::process::exit(EX_OSERR);
     }
 }
 
-fn run_file(path: &str) {
-    let contents = fs::read_to_string(path).unwrap();
-    run(&contents);
+fn usage() -> ! {
+    eprintln!("Usage: lox [--vm] [--max-depth N] [script]");
+    std::process::exit(EX_USAGE);
 }
 
-fn run_prompt() {
+fn run_file(path: &str, options: Options) {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) => {
+            eprintln!("Could not read '{}': {}", path, error);
+            std::process::exit(EX_NOINPUT);
+        }
+    };
+    run(path, &contents, options);
+}
+
+/// A long-lived interpreter on either backend, so definitions persist between REPL entries.
+enum Session {
+    TreeWalker(Lox),
+    Vm(Vm),
+}
+
+impl Session {
+    fn new(options: Options) -> Self {
+        match options.backend {
+            Backend::TreeWalker => {
+                let mut lox = Lox::new();
+                if let Some(depth) = options.max_call_depth {
+                    lox.interpreter_mut().set_max_call_depth(depth);
+                }
+                Session::TreeWalker(lox)
+            }
+            Backend::Vm => {
+                let mut vm = Vm::new();
+                if let Some(depth) = options.max_call_depth {
+                    vm.set_max_call_depth(depth);
+                }
+                Session::Vm(vm)
+            }
+        }
+    }
+
+    fn run(&mut self, source: &str) -> Result<(), LoxError> {
+        match self {
+            Session::TreeWalker(lox) => lox.eval(source).map(|_| ()),
+            Session::Vm(vm) => vm.interpret(source),
+        }
+    }
+
+    /// Evaluates a bare expression and prints its value.
+    fn echo(&mut self, expression: &str) {
+        let (source, result) = match self {
+            Session::TreeWalker(lox) => {
+                let source = format!("{};", expression);
+                let result = lox.eval(&source).map(|value| println!("{}", value));
+                (source, result)
+            }
+            Session::Vm(vm) => {
+                let source = format!("print {};", expression);
+                let result = vm.interpret(&source);
+                (source, result)
+            }
+        };
+        if let Err(error) = result {
+            report_error(&source, None, error);
+        }
+    }
+}
+
+fn run_prompt(options: Options) {
+    let mut editor = DefaultEditor::new().expect("Failed to set up the line editor");
+    let history = history_path();
+    if let Some(history) = &history {
+        // A missing history file just means this is the first session.
+        let _ = editor.load_history(history);
+    }
+
+    let mut session = Session::new(options);
+    let mut buffer = String::new();
     loop {
-        let mut buffer = String::new();
-        io::stdin().read_line(&mut buffer).unwrap();
-        run(&buffer);
+        let prompt = if buffer.is_empty() { "> " } else { "... " };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                buffer.push_str(&line);
+                buffer.push('\n');
+                if is_incomplete(&buffer) {
+                    continue;
+                }
+
+                let source = std::mem::take(&mut buffer);
+                let _ = editor.add_history_entry(source.trim_end());
+                if is_bare_expression(&source) {
+                    session.echo(source.trim_end());
+                } else if let Err(error) = session.run(&source) {
+                    report_error(&source, None, error);
+                }
+            }
+            Err(ReadlineError::Interrupted) => buffer.clear(),
+            Err(ReadlineError::Eof) => break,
+            Err(error) => {
+                eprintln!("{}", error);
+                break;
+            }
+        }
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
     }
 }
 
-fn run(source: &str) {
-    let mut scanner = Scanner::new(source);
-    let tokens = scanner.scan_tokens();
-    let mut parser = Parser::new(tokens);
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".lox_history"))
+}
 
-    let statements = parser.parse();
-    let interpreter = Interpreter::new();
-    interpreter.interpret(&statements);
+/// Whether `source` has more opening than closing parens or braces, so the REPL should keep
+/// reading lines before running it.
+fn is_incomplete(source: &str) -> bool {
+    let mut depth = 0;
+    let (tokens, _) = Scanner::new(source).scan_tokens();
+    for token in tokens {
+        match token.token_type {
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => depth -= 1,
+            _ => (),
+        }
+    }
+    depth > 0
+}
+
+fn is_bare_expression(source: &str) -> bool {
+    let source = source.trim_end();
+    if source.ends_with(';') || source.ends_with('}') {
+        return false;
+    }
+    let (tokens, errors) = Scanner::new(source).scan_tokens();
+    errors.is_empty() && Parser::new(tokens).parse_expression().is_ok()
+}
+
+fn run(path: &str, source: &str, options: Options) {
+    if let Err(error) = Session::new(options).run(source) {
+        let code = match error {
+            LoxError::Scan(_) | LoxError::Parse(_) | LoxError::Resolve(_) => EX_DATAERR,
+            LoxError::Runtime(_) | LoxError::ResourceExhausted(_) | LoxError::Other(_) => {
+                EX_SOFTWARE
+            }
+        };
+        report_error(source, Some(path), error);
+        std::process::exit(code);
+    }
+}
+
+/// Prints `error` to stderr. `path` names the script in stack traces; errors from the REPL
+/// show bare line numbers instead.
+fn report_error(source: &str, path: Option<&str>, error: LoxError) {
+    match error {
+        LoxError::Scan(errors) => {
+            for error in errors {
+                self::error(error.line, &error.message);
+                eprint!("{}", diagnostic::underline(source, &error.span));
+            }
+        }
+        LoxError::Parse(errors) => {
+            for error in errors {
+                token_error(source, &error.token, &error.message);
+            }
+        }
+        LoxError::Resolve(errors) => {
+            for error in errors {
+                token_error(source, &error.token, &error.message);
+            }
+        }
+        LoxError::Runtime(error) => {
+            eprintln!("{}", error);
+            eprint!("{}", diagnostic::underline(source, error.span()));
+            // Deep recursion repeats the same frame many times over, so long runs are shortened.
+            for run in error.trace().chunk_by(|a, b| a == b) {
+                for frame in run.iter().take(3) {
+                    match path {
+                        Some(path) => {
+                            eprintln!("    at {} ({}:{})", frame.function, path, frame.line)
+                        }
+                        None => eprintln!("    at {} (line {})", frame.function, frame.line),
+                    }
+                }
+                if run.len() > 3 {
+                    eprintln!("    ... repeated {} more times", run.len() - 3);
+                }
+            }
+        }
+        LoxError::ResourceExhausted(error) => eprintln!("{}", error),
+        LoxError::Other(error) => eprintln!("{}", error),
+    }
 }
 
 fn error(line: usize, message: &str) {
     report(line, "", message);
 }
 
+fn token_error(source: &str, token: &Token, message: &str) {
+    report(token.line, &token.location(), message);
+    eprint!("{}", diagnostic::underline(source, &token.span));
+}
+
 fn report(line: usize, location: &str, message: &str) {
     eprintln!("[line {}] Error{}: {}", line, location, message);
 }
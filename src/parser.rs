@@ -1,79 +1,222 @@
+use std::rc::Rc;
+
+use thiserror::Error;
+
 use crate::{
     expr::Expr,
-    stmt::Stmt,
+    stack::StackLimit,
+    stmt::{Catch, Stmt},
     token::{Token, Value},
     token_type::TokenType,
 };
 
+/// How deeply declarations and expressions may nest. Both backends compile and run code by
+/// recursing as deeply as it nests, so the limit keeps nesting the same everywhere unless a
+/// thread's stack is too small even for that.
+pub(crate) const MAX_NESTING: usize = 256;
+
+#[derive(Error, Debug)]
+#[error("[line {}] Error{}: {}", token.line, token.location(), message)]
+pub struct ParseError {
+    /// Boxed to keep the error, and every `Result` the parser returns, small.
+    pub token: Box<Token>,
+    pub message: String,
+}
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    errors: Vec<ParseError>,
+    /// How many declarations and expressions enclose the one being parsed.
+    nesting: usize,
+    stack_limit: StackLimit,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        Self {
+            tokens,
+            current: 0,
+            errors: vec![],
+            nesting: 0,
+            stack_limit: StackLimit::current(),
+        }
     }
 
-    pub fn parse(&mut self) -> Vec<Stmt> {
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<ParseError>> {
         let mut statements = vec![];
         while !self.is_at_end() {
-            statements.push(self.declaration());
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
+        }
+
+        if self.errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(std::mem::take(&mut self.errors))
         }
-        statements
     }
 
-    fn declaration(&mut self) -> Stmt {
-        if self.do_match(&[TokenType::Fun]) {
+    /// Parses the tokens as a single expression with nothing following it, such as a bare
+    /// expression typed at the REPL.
+    pub fn parse_expression(&mut self) -> Result<Expr, Vec<ParseError>> {
+        let expression = self.expression().map_err(|error| vec![error])?;
+        if !self.is_at_end() {
+            return Err(vec![self.error(self.peek(), "Expect end of expression.")]);
+        }
+
+        if self.errors.is_empty() {
+            Ok(expression)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn declaration(&mut self) -> Option<Stmt> {
+        let result = if self.do_match(&[TokenType::Class]) {
+            self.class_declaration()
+        } else if self.check(&TokenType::Fun) && !self.check_next(&TokenType::LeftParen) {
+            self.advance();
             self.function("function")
         } else if self.do_match(&[TokenType::Var]) {
             self.var_declaration()
         } else {
             self.statement()
+        };
+
+        match result {
+            Ok(statement) => Some(statement),
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize();
+                None
+            }
+        }
+    }
+
+    fn class_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.consume(TokenType::Identifier, "Expect class name.")?;
+
+        let mut superclass = None;
+        if self.do_match(&[TokenType::Less]) {
+            self.consume(TokenType::Identifier, "Expect superclass name.")?;
+            superclass = Some(Expr::Variable {
+                name: self.previous(),
+                slot: None,
+            });
+        }
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+
+        let mut methods = vec![];
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            methods.push(self.function("method")?);
         }
+
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
+        Ok(Stmt::Class {
+            name,
+            superclass,
+            methods,
+        })
+    }
+
+    fn function(&mut self, kind: &str) -> Result<Stmt, ParseError> {
+        self.nested(|parser| parser.function_declaration(kind))
     }
 
-    fn function(&mut self, kind: &str) -> Stmt {
-        let name = self.consume(TokenType::Identifier, "Expect function name");
-        self.consume(TokenType::LeftParen, "Expect '(' after function name.");
+    fn function_declaration(&mut self, kind: &str) -> Result<Stmt, ParseError> {
+        let name = self.consume(TokenType::Identifier, &format!("Expect {} name.", kind))?;
+        self.consume(
+            TokenType::LeftParen,
+            &format!("Expect '(' after {} name.", kind),
+        )?;
+        let params = self.parameters()?;
+        self.consume(
+            TokenType::LeftBrace,
+            &format!("Expect '{{' before {} body.", kind),
+        )?;
+        let body = self.block()?.into();
+        Ok(Stmt::Function { name, params, body })
+    }
+
+    /// Parses a parameter list up to and including its closing `)`.
+    fn parameters(&mut self) -> Result<Vec<Token>, ParseError> {
         let mut parameters = vec![];
         if !self.check(&TokenType::RightParen) {
             loop {
                 if parameters.len() >= 255 {
-                    eprintln!("Can't have more than 255 parameters.");
+                    let error = self.error(self.peek(), "Can't have more than 255 parameters.");
+                    self.errors.push(error);
                 }
-                parameters.push(self.consume(TokenType::Identifier, "Expect parameter name."));
+                parameters.push(self.consume(TokenType::Identifier, "Expect parameter name.")?);
                 if !self.do_match(&[TokenType::Comma]) {
                     break;
                 }
             }
         }
-        self.consume(TokenType::RightParen, "Expect ')' after parameters.");
-        self.consume(TokenType::LeftBrace, "Expect '{' before function body.");
-        let body = self.block();
-        Stmt::Function {
-            name,
-            params: parameters,
-            body,
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+        Ok(parameters)
+    }
+
+    /// Whether the tokens ahead are the parameter list of an arrow function, `(a, b) =>`. Arrow
+    /// functions and parenthesized expressions can't be told apart until the `=>`.
+    fn is_arrow_function(&self) -> bool {
+        if !self.check(&TokenType::LeftParen) {
+            return false;
+        }
+
+        let token_type = |offset: usize| {
+            self.tokens
+                .get(self.current + offset)
+                .map(|token| token.token_type)
+        };
+        let mut offset = 1;
+        if token_type(offset) != Some(TokenType::RightParen) {
+            loop {
+                if token_type(offset) != Some(TokenType::Identifier) {
+                    return false;
+                }
+                offset += 1;
+                match token_type(offset) {
+                    Some(TokenType::Comma) => offset += 1,
+                    Some(TokenType::RightParen) => break,
+                    _ => return false,
+                }
+            }
         }
+        token_type(offset + 1) == Some(TokenType::Arrow)
     }
 
-    fn var_declaration(&mut self) -> Stmt {
-        let name = self.consume(TokenType::Identifier, "Expect variable name.");
+    fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let name = self.consume(TokenType::Identifier, "Expect variable name.")?;
         let mut initializer = None;
         if self.do_match(&[TokenType::Equal]) {
-            initializer = Some(self.expression());
+            initializer = Some(self.expression()?);
         }
 
         self.consume(
             TokenType::Semicolon,
             "Expect ';' after variable declaration.",
-        );
-        Stmt::Var { name, initializer }
+        )?;
+        Ok(Stmt::Var { name, initializer })
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        self.nested(Self::nested_statement)
     }
 
-    fn statement(&mut self) -> Stmt {
-        if self.do_match(&[TokenType::For]) {
+    fn nested_statement(&mut self) -> Result<Stmt, ParseError> {
+        if self.do_match(&[TokenType::Break]) {
+            let keyword = self.previous();
+            self.consume(TokenType::Semicolon, "Expect ';' after 'break'.")?;
+            Ok(Stmt::Break { keyword })
+        } else if self.do_match(&[TokenType::Continue]) {
+            let keyword = self.previous();
+            self.consume(TokenType::Semicolon, "Expect ';' after 'continue'.")?;
+            Ok(Stmt::Continue { keyword })
+        } else if self.do_match(&[TokenType::For]) {
             self.for_statement()
         } else if self.do_match(&[TokenType::If]) {
             self.if_statement()
@@ -81,68 +224,101 @@ impl Parser {
             self.print_statement()
         } else if self.do_match(&[TokenType::Return]) {
             self.return_statement()
+        } else if self.do_match(&[TokenType::Throw]) {
+            self.throw_statement()
+        } else if self.do_match(&[TokenType::Try]) {
+            self.try_statement()
         } else if self.do_match(&[TokenType::While]) {
             self.while_statement()
         } else if self.do_match(&[TokenType::LeftBrace]) {
-            Stmt::Block {
-                statements: self.block(),
-            }
+            Ok(Stmt::Block {
+                statements: self.block()?,
+            })
         } else {
             self.expression_statement()
         }
     }
 
-    fn return_statement(&mut self) -> Stmt {
+    fn return_statement(&mut self) -> Result<Stmt, ParseError> {
         let keyword = self.previous();
         let mut value = None;
         if !self.check(&TokenType::Semicolon) {
-            value = Some(self.expression());
+            value = Some(self.expression()?);
         }
-        self.consume(TokenType::Semicolon, "Expect ';' after return value.");
-        Stmt::Return { keyword, value }
+        self.consume(TokenType::Semicolon, "Expect ';' after return value.")?;
+        Ok(Stmt::Return { keyword, value })
     }
 
-    fn for_statement(&mut self) -> Stmt {
-        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.");
+    fn throw_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.previous();
+        let value = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after thrown value.")?;
+        Ok(Stmt::Throw { keyword, value })
+    }
 
-        let mut initializer = None;
-        if self.do_match(&[TokenType::Semicolon]) {
-            initializer = None;
+    fn try_statement(&mut self) -> Result<Stmt, ParseError> {
+        self.consume(TokenType::LeftBrace, "Expect '{' after 'try'.")?;
+        let body = self.block()?;
+
+        let mut catch = None;
+        if self.do_match(&[TokenType::Catch]) {
+            self.consume(TokenType::LeftParen, "Expect '(' after 'catch'.")?;
+            let name = self.consume(TokenType::Identifier, "Expect exception name.")?;
+            self.consume(TokenType::RightParen, "Expect ')' after exception name.")?;
+            self.consume(TokenType::LeftBrace, "Expect '{' before catch body.")?;
+            catch = Some(Catch {
+                name,
+                body: self.block()?,
+            });
+        }
+
+        let mut finally = None;
+        if self.do_match(&[TokenType::Finally]) {
+            self.consume(TokenType::LeftBrace, "Expect '{' after 'finally'.")?;
+            finally = Some(self.block()?);
+        }
+
+        if catch.is_none() && finally.is_none() {
+            return Err(self.error(self.peek(), "Expect 'catch' or 'finally' after try block."));
+        }
+
+        Ok(Stmt::Try {
+            body,
+            catch,
+            finally,
+        })
+    }
+
+    fn for_statement(&mut self) -> Result<Stmt, ParseError> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
+
+        let initializer = if self.do_match(&[TokenType::Semicolon]) {
+            None
         } else if self.do_match(&[TokenType::Var]) {
-            initializer = Some(self.var_declaration());
+            Some(self.var_declaration()?)
         } else {
-            initializer = Some(self.expression_statement());
-        }
+            Some(self.expression_statement()?)
+        };
 
         let mut condition = Expr::Literal {
             value: Value::from_bool(true),
+            span: self.peek().span,
         };
-        if !self.do_match(&[TokenType::Semicolon]) {
-            condition = self.expression();
+        if !self.check(&TokenType::Semicolon) {
+            condition = self.expression()?;
         }
-        self.consume(TokenType::Semicolon, "Expect ';' after loop condition.");
+        self.consume(TokenType::Semicolon, "Expect ';' after loop condition.")?;
 
         let mut increment = None;
-        if !self.do_match(&[TokenType::RightParen]) {
-            increment = Some(self.expression());
-        }
-        self.consume(TokenType::RightParen, "Expect ')' after for clauses.");
-
-        let mut body = self.statement();
-        if let Some(increment) = increment {
-            body = Stmt::Block {
-                statements: vec![
-                    body,
-                    Stmt::Expression {
-                        expression: increment,
-                    },
-                ],
-            };
+        if !self.check(&TokenType::RightParen) {
+            increment = Some(self.expression()?);
         }
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
 
-        body = Stmt::While {
+        let mut body = Stmt::While {
             condition,
-            body: Box::new(body),
+            body: Box::new(self.statement()?),
+            increment,
         };
 
         if let Some(initializer) = initializer {
@@ -151,92 +327,119 @@ impl Parser {
             };
         }
 
-        body
+        Ok(body)
     }
 
-    fn while_statement(&mut self) -> Stmt {
-        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.");
-        let condition = self.expression();
-        self.consume(TokenType::RightParen, "Expect ')' after condition.");
-        let body = self.statement();
+    fn while_statement(&mut self) -> Result<Stmt, ParseError> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
+        let body = self.statement()?;
 
-        Stmt::While {
+        Ok(Stmt::While {
             condition,
             body: Box::new(body),
-        }
+            increment: None,
+        })
     }
 
-    fn if_statement(&mut self) -> Stmt {
-        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.");
-        let condition = self.expression();
-        self.consume(TokenType::RightParen, "Expect ')' after 'if' condition.");
+    fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after 'if' condition.")?;
 
-        let then_branch = self.statement();
+        let then_branch = self.statement()?;
         let mut else_branch = None;
         if self.do_match(&[TokenType::Else]) {
-            else_branch = Some(Box::new(self.statement()));
+            else_branch = Some(Box::new(self.statement()?));
         }
 
-        Stmt::If {
+        Ok(Stmt::If {
             condition,
             then_branch: Box::new(then_branch),
             else_branch,
-        }
+        })
     }
 
-    fn block(&mut self) -> Vec<Stmt> {
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
         let mut statements = vec![];
 
         while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
-            statements.push(self.declaration());
+            if let Some(statement) = self.declaration() {
+                statements.push(statement);
+            }
         }
 
-        self.consume(TokenType::RightBrace, "Expect '}' after block.");
-        statements
+        self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
+        Ok(statements)
     }
 
-    fn print_statement(&mut self) -> Stmt {
-        let value = self.expression();
-        self.consume(TokenType::Semicolon, "Expect ';' after value.");
-        Stmt::Print { expression: value }
+    fn print_statement(&mut self) -> Result<Stmt, ParseError> {
+        let value = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+        Ok(Stmt::Print { expression: value })
     }
 
-    fn expression_statement(&mut self) -> Stmt {
-        let expr = self.expression();
-        self.consume(TokenType::Semicolon, "Expect ';' after expression.");
-        Stmt::Expression { expression: expr }
+    fn expression_statement(&mut self) -> Result<Stmt, ParseError> {
+        let expr = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
+        Ok(Stmt::Expression { expression: expr })
     }
 
-    fn expression(&mut self) -> Expr {
-        self.assignment()
+    fn expression(&mut self) -> Result<Expr, ParseError> {
+        self.nested(Self::assignment)
     }
 
-    fn assignment(&mut self) -> Expr {
-        let expr = self.or();
+    fn assignment(&mut self) -> Result<Expr, ParseError> {
+        let expr = self.or()?;
 
         if self.do_match(&[TokenType::Equal]) {
             let equals = self.previous();
-            let value = self.assignment();
-
-            if let Expr::Variable { name } = expr {
-                return Expr::Assign {
-                    name,
-                    value: Box::new(value),
-                };
+            let value = self.nested(Self::assignment)?;
+
+            match expr {
+                Expr::Variable { name, .. } => {
+                    return Ok(Expr::Assign {
+                        name,
+                        value: Box::new(value),
+                        slot: None,
+                    })
+                }
+                Expr::Get { object, name } => {
+                    return Ok(Expr::Set {
+                        object,
+                        name,
+                        value: Box::new(value),
+                    })
+                }
+                Expr::Index {
+                    object,
+                    bracket,
+                    index,
+                } => {
+                    return Ok(Expr::SetIndex {
+                        object,
+                        bracket,
+                        index,
+                        value: Box::new(value),
+                    })
+                }
+                _ => {
+                    let error = self.error(equals, "Invalid assignment target.");
+                    self.errors.push(error);
+                }
             }
-
-            panic!("Invalid assignment target.");
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn or(&mut self) -> Expr {
-        let mut expr = self.and();
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.and()?;
 
         while self.do_match(&[TokenType::Or]) {
             let operator = self.previous();
-            let right = self.and();
+            let right = self.and()?;
             expr = Expr::Logical {
                 left: Box::new(expr),
                 operator,
@@ -244,15 +447,15 @@ impl Parser {
             };
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn and(&mut self) -> Expr {
-        let mut expr = self.equality();
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.equality()?;
 
         while self.do_match(&[TokenType::And]) {
             let operator = self.previous();
-            let right = self.equality();
+            let right = self.equality()?;
             expr = Expr::Logical {
                 left: Box::new(expr),
                 operator,
@@ -260,14 +463,14 @@ impl Parser {
             };
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn equality(&mut self) -> Expr {
-        let mut expr = self.comparison();
+    fn equality(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.comparison()?;
         while self.do_match(&[TokenType::BangEqual, TokenType::EqualEqual]) {
             let operator = self.previous();
-            let right = self.comparison();
+            let right = self.comparison()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
@@ -275,11 +478,11 @@ impl Parser {
             };
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn comparison(&mut self) -> Expr {
-        let mut expr = self.term();
+    fn comparison(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.term()?;
 
         while self.do_match(&[
             TokenType::Greater,
@@ -288,7 +491,7 @@ impl Parser {
             TokenType::LessEqual,
         ]) {
             let operator = self.previous();
-            let right = self.term();
+            let right = self.term()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
@@ -296,15 +499,15 @@ impl Parser {
             };
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn term(&mut self) -> Expr {
-        let mut expr = self.factor();
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.factor()?;
 
         while self.do_match(&[TokenType::Minus, TokenType::Plus]) {
             let operator = self.previous();
-            let right = self.factor();
+            let right = self.factor()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
@@ -312,100 +515,257 @@ impl Parser {
             };
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn factor(&mut self) -> Expr {
-        let mut expr = self.unary();
+    fn factor(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.unary()?;
 
         while self.do_match(&[TokenType::Slash, TokenType::Star]) {
             let operator = self.previous();
-            let right = self.unary();
+            let right = self.unary()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
                 right: Box::new(right),
             };
         }
-        expr
+        Ok(expr)
     }
 
-    fn unary(&mut self) -> Expr {
+    fn unary(&mut self) -> Result<Expr, ParseError> {
         if self.do_match(&[TokenType::Bang, TokenType::Minus]) {
             let operator = self.previous();
-            let right = self.unary();
-            Expr::Unary {
+            let right = self.nested(Self::unary)?;
+            Ok(Expr::Unary {
                 operator,
                 right: Box::new(right),
-            }
+            })
         } else {
             self.call()
         }
     }
 
-    fn call(&mut self) -> Expr {
-        let mut expr = self.primary();
+    fn call(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.primary()?;
 
         loop {
             if self.do_match(&[TokenType::LeftParen]) {
-                expr = self.finish_call(expr);
+                expr = self.finish_call(expr)?;
+            } else if self.do_match(&[TokenType::Dot]) {
+                let name =
+                    self.consume(TokenType::Identifier, "Expect property name after '.'.")?;
+                expr = Expr::Get {
+                    object: Box::new(expr),
+                    name,
+                };
+            } else if self.do_match(&[TokenType::LeftBracket]) {
+                let index = self.expression()?;
+                let bracket = self.consume(TokenType::RightBracket, "Expect ']' after index.")?;
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    bracket,
+                    index: Box::new(index),
+                };
             } else {
                 break;
             }
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn finish_call(&mut self, callee: Expr) -> Expr {
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr, ParseError> {
         let mut arguments = vec![];
 
         if !self.check(&TokenType::RightParen) {
             loop {
                 if arguments.len() >= 255 {
-                    eprintln!("Can't have more than 255 arguments");
+                    let error = self.error(self.peek(), "Can't have more than 255 arguments.");
+                    self.errors.push(error);
                 }
-                arguments.push(self.expression());
+                arguments.push(self.expression()?);
                 if !self.do_match(&[TokenType::Comma]) {
                     break;
                 }
             }
         }
-        let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.");
+        let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
 
-        Expr::Call {
+        Ok(Expr::Call {
             callee: Box::new(callee),
             paren,
             arguments,
-        }
+        })
     }
 
-    fn primary(&mut self) -> Expr {
-        if self.do_match(&[TokenType::False]) {
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let expr = if self.do_match(&[TokenType::False]) {
             Expr::Literal {
                 value: Value::False,
+                span: self.previous().span,
             }
         } else if self.do_match(&[TokenType::True]) {
-            Expr::Literal { value: Value::True }
+            Expr::Literal {
+                value: Value::True,
+                span: self.previous().span,
+            }
         } else if self.do_match(&[TokenType::Nil]) {
-            Expr::Literal { value: Value::None }
+            Expr::Literal {
+                value: Value::None,
+                span: self.previous().span,
+            }
         } else if self.do_match(&[TokenType::Number, TokenType::String]) {
+            let token = self.previous();
             Expr::Literal {
-                value: self.previous().literal,
+                value: token.literal,
+                span: token.span,
+            }
+        } else if self.do_match(&[TokenType::Interpolation]) {
+            let start = self.previous();
+            let mut parts = vec![];
+            loop {
+                let segment = self.previous();
+                if segment.literal != Value::String("".into()) {
+                    parts.push(Expr::Literal {
+                        value: segment.literal,
+                        span: segment.span,
+                    });
+                }
+                parts.push(self.expression()?);
+
+                if self.do_match(&[TokenType::Interpolation]) {
+                    continue;
+                }
+                let end = self.consume(TokenType::String, "Expect '}' after interpolation.")?;
+                if end.literal != Value::String("".into()) {
+                    parts.push(Expr::Literal {
+                        value: end.literal,
+                        span: end.span.clone(),
+                    });
+                }
+                break Expr::Interpolation {
+                    span: start.span.start..end.span.end,
+                    start,
+                    parts,
+                };
+            }
+        } else if self.do_match(&[TokenType::Super]) {
+            let keyword = self.previous();
+            self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+            let method = self.consume(TokenType::Identifier, "Expect superclass method name.")?;
+            Expr::Super {
+                keyword,
+                method,
+                slot: None,
+            }
+        } else if self.do_match(&[TokenType::This]) {
+            Expr::This {
+                keyword: self.previous(),
+                slot: None,
             }
         } else if self.do_match(&[TokenType::Identifier]) {
             Expr::Variable {
                 name: self.previous(),
+                slot: None,
+            }
+        } else if self.do_match(&[TokenType::Fun]) {
+            let keyword = self.previous();
+            self.consume(TokenType::LeftParen, "Expect '(' after 'fun'.")?;
+            let params = self.parameters()?;
+            self.consume(TokenType::LeftBrace, "Expect '{' before function body.")?;
+            let body = self.block()?.into();
+            Expr::Function {
+                span: keyword.span.start..self.previous().span.end,
+                keyword,
+                params,
+                body,
+            }
+        } else if self.is_arrow_function() {
+            let keyword = self.advance();
+            let params = self.parameters()?;
+            let arrow = self.consume(TokenType::Arrow, "Expect '=>' after parameters.")?;
+            let value = self.expression()?;
+            Expr::Function {
+                span: keyword.span.start..value.span().end,
+                keyword,
+                params,
+                body: Rc::new([Stmt::Return {
+                    keyword: arrow,
+                    value: Some(value),
+                }]),
             }
         } else if self.do_match(&[TokenType::LeftParen]) {
-            let expr = self.expression();
-            self.consume(TokenType::RightParen, "Expect ')' after expression.");
+            let left_paren = self.previous();
+            let expr = self.expression()?;
+            let right_paren =
+                self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
             Expr::Grouping {
                 expression: Box::new(expr),
+                span: left_paren.span.start..right_paren.span.end,
+            }
+        } else if self.do_match(&[TokenType::LeftBracket]) {
+            let left_bracket = self.previous();
+            let mut elements = vec![];
+            if !self.check(&TokenType::RightBracket) {
+                loop {
+                    elements.push(self.expression()?);
+                    if !self.do_match(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            let right_bracket =
+                self.consume(TokenType::RightBracket, "Expect ']' after list elements.")?;
+            Expr::List {
+                span: left_bracket.span.start..right_bracket.span.end,
+                bracket: left_bracket,
+                elements,
+            }
+        } else if self.do_match(&[TokenType::LeftBrace]) {
+            // A brace can only start a map here: at the start of a statement it is always parsed
+            // as a block before expression parsing is reached.
+            let left_brace = self.previous();
+            let mut entries = vec![];
+            if !self.check(&TokenType::RightBrace) {
+                loop {
+                    let key = self.expression()?;
+                    self.consume(TokenType::Colon, "Expect ':' after map key.")?;
+                    let value = self.expression()?;
+                    entries.push((key, value));
+                    if !self.do_match(&[TokenType::Comma]) {
+                        break;
+                    }
+                }
+            }
+            let right_brace =
+                self.consume(TokenType::RightBrace, "Expect '}' after map entries.")?;
+            Expr::Map {
+                span: left_brace.span.start..right_brace.span.end,
+                brace: left_brace,
+                entries,
             }
         } else {
-            panic!("parsing failure");
+            return Err(self.error(self.peek(), "Expect expression."));
+        };
+
+        Ok(expr)
+    }
+
+    /// Parses with `parse` one level of nesting deeper, failing once that passes `MAX_NESTING`
+    /// or would leave too little native stack.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.nesting == MAX_NESTING || self.stack_limit.exceeded() {
+            return Err(self.error(self.peek(), "Too much nesting."));
         }
+
+        self.nesting += 1;
+        let result = parse(self);
+        self.nesting -= 1;
+        result
     }
 
     fn do_match(&mut self, types: &[TokenType]) -> bool {
@@ -426,6 +786,12 @@ impl Parser {
         }
     }
 
+    fn check_next(&self, tt: &TokenType) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|token| token.token_type == *tt)
+    }
+
     fn advance(&mut self) -> Token {
         if !self.is_at_end() {
             self.current += 1;
@@ -445,11 +811,46 @@ impl Parser {
         self.tokens[self.current - 1].clone()
     }
 
-    fn consume(&mut self, tt: TokenType, msg: &str) -> Token {
+    fn consume(&mut self, tt: TokenType, msg: &str) -> Result<Token, ParseError> {
         if self.check(&tt) {
-            self.advance()
+            Ok(self.advance())
         } else {
-            panic!("{}", msg.to_string());
+            Err(self.error(self.peek(), msg))
+        }
+    }
+
+    fn error(&self, token: Token, message: &str) -> ParseError {
+        ParseError {
+            token: Box::new(token),
+            message: message.to_string(),
+        }
+    }
+
+    fn synchronize(&mut self) {
+        self.advance();
+
+        while !self.is_at_end() {
+            if self.previous().token_type == TokenType::Semicolon {
+                return;
+            }
+
+            match self.peek().token_type {
+                TokenType::Break
+                | TokenType::Class
+                | TokenType::Continue
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::Throw
+                | TokenType::Try => return,
+                _ => {
+                    self.advance();
+                }
+            }
         }
     }
 }
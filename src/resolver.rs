@@ -0,0 +1,386 @@
+use std::{collections::HashMap, rc::Rc};
+
+use thiserror::Error;
+
+use crate::{
+    expr::{Expr, Slot},
+    stmt::Stmt,
+    token::Token,
+};
+
+#[derive(Error, Debug)]
+#[error("[line {}] Error{}: {}", token.line, token.location(), message)]
+pub struct ResolveError {
+    pub token: Token,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Initializer,
+    Method,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+/// A local declared in a scope: whether its initializer has finished and the slot it occupies in
+/// the scope's environment.
+struct Local {
+    defined: bool,
+    index: usize,
+}
+
+pub struct Resolver {
+    scopes: Vec<HashMap<String, Local>>,
+    current_function: FunctionType,
+    current_class: ClassType,
+    /// Whether `break` and `continue` have a loop to apply to. Reset on entering a function, as
+    /// they can't jump out of one.
+    in_loop: bool,
+    errors: Vec<ResolveError>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![],
+            current_function: FunctionType::None,
+            current_class: ClassType::None,
+            in_loop: false,
+            errors: vec![],
+        }
+    }
+
+    pub fn resolve(&mut self, statements: &mut [Stmt]) -> Result<(), Vec<ResolveError>> {
+        self.resolve_statements(statements);
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn resolve_statements(&mut self, statements: &mut [Stmt]) {
+        for statement in statements {
+            self.resolve_statement(statement);
+        }
+    }
+
+    fn resolve_statement(&mut self, statement: &mut Stmt) {
+        match statement {
+            Stmt::Block { statements } => {
+                self.begin_scope();
+                self.resolve_statements(statements);
+                self.end_scope();
+            }
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                let enclosing_class = self.current_class;
+                self.current_class = ClassType::Class;
+
+                self.declare(name);
+                self.define(name);
+
+                if let Some(superclass) = superclass {
+                    self.current_class = ClassType::Subclass;
+                    self.resolve_expression(superclass);
+
+                    self.begin_scope();
+                    self.define_name("super");
+                }
+
+                self.begin_scope();
+                self.define_name("this");
+
+                for method in methods {
+                    if let Stmt::Function { name, params, body } = method {
+                        let declaration = if name.lexeme == "init" {
+                            FunctionType::Initializer
+                        } else {
+                            FunctionType::Method
+                        };
+                        self.resolve_function(params, body, declaration);
+                    }
+                }
+
+                self.end_scope();
+
+                if superclass.is_some() {
+                    self.end_scope();
+                }
+
+                self.current_class = enclosing_class;
+            }
+            Stmt::Expression { expression } => self.resolve_expression(expression),
+            Stmt::Function { name, params, body } => {
+                self.declare(name);
+                self.define(name);
+                self.resolve_function(params, body, FunctionType::Function);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_expression(condition);
+                self.resolve_statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_statement(else_branch);
+                }
+            }
+            Stmt::Print { expression } => self.resolve_expression(expression),
+            Stmt::Return { keyword, value } => {
+                if self.current_function == FunctionType::None {
+                    self.error(keyword, "Can't return from top-level code.");
+                }
+
+                if let Some(value) = value {
+                    if self.current_function == FunctionType::Initializer {
+                        self.error(keyword, "Can't return a value from an initializer.");
+                    }
+                    self.resolve_expression(value);
+                }
+            }
+            Stmt::Throw { value, .. } => self.resolve_expression(value),
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.begin_scope();
+                self.resolve_statements(body);
+                self.end_scope();
+
+                if let Some(catch) = catch {
+                    self.begin_scope();
+                    self.declare(&catch.name);
+                    self.define(&catch.name);
+                    self.resolve_statements(&mut catch.body);
+                    self.end_scope();
+                }
+
+                if let Some(finally) = finally {
+                    self.begin_scope();
+                    self.resolve_statements(finally);
+                    self.end_scope();
+                }
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => {
+                self.resolve_expression(condition);
+
+                let enclosing_loop = std::mem::replace(&mut self.in_loop, true);
+                self.resolve_statement(body);
+                self.in_loop = enclosing_loop;
+
+                if let Some(increment) = increment {
+                    self.resolve_expression(increment);
+                }
+            }
+            Stmt::Break { keyword } => {
+                if !self.in_loop {
+                    self.error(keyword, "Can't use 'break' outside of a loop.");
+                }
+            }
+            Stmt::Continue { keyword } => {
+                if !self.in_loop {
+                    self.error(keyword, "Can't use 'continue' outside of a loop.");
+                }
+            }
+            Stmt::Var { name, initializer } => {
+                self.declare(name);
+                if let Some(initializer) = initializer {
+                    self.resolve_expression(initializer);
+                }
+                self.define(name);
+            }
+        }
+    }
+
+    fn resolve_function(
+        &mut self,
+        params: &[Token],
+        body: &mut Rc<[Stmt]>,
+        function_type: FunctionType,
+    ) {
+        // Copies the body only if the statements were cloned before being resolved.
+        let body = Rc::make_mut(body);
+        let enclosing_function = self.current_function;
+        self.current_function = function_type;
+        let enclosing_loop = std::mem::replace(&mut self.in_loop, false);
+
+        self.begin_scope();
+        for param in params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.resolve_statements(body);
+        self.end_scope();
+
+        self.current_function = enclosing_function;
+        self.in_loop = enclosing_loop;
+    }
+
+    fn resolve_expression(&mut self, expression: &mut Expr) {
+        match expression {
+            Expr::Assign { name, value, slot } => {
+                self.resolve_expression(value);
+                *slot = self.resolve_local(name);
+            }
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.resolve_expression(left);
+                self.resolve_expression(right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.resolve_expression(callee);
+                for argument in arguments {
+                    self.resolve_expression(argument);
+                }
+            }
+            Expr::Function { params, body, .. } => {
+                self.resolve_function(params, body, FunctionType::Function)
+            }
+            Expr::Get { object, .. } => self.resolve_expression(object),
+            Expr::Grouping { expression, .. } => self.resolve_expression(expression),
+            Expr::Index { object, index, .. } => {
+                self.resolve_expression(object);
+                self.resolve_expression(index);
+            }
+            Expr::Interpolation { parts, .. }
+            | Expr::List {
+                elements: parts, ..
+            } => {
+                for part in parts {
+                    self.resolve_expression(part);
+                }
+            }
+            Expr::Literal { .. } => (),
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.resolve_expression(key);
+                    self.resolve_expression(value);
+                }
+            }
+            Expr::Set { object, value, .. } => {
+                self.resolve_expression(value);
+                self.resolve_expression(object);
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.resolve_expression(value);
+                self.resolve_expression(object);
+                self.resolve_expression(index);
+            }
+            Expr::Super { keyword, slot, .. } => {
+                if self.current_class == ClassType::None {
+                    self.error(keyword, "Can't use 'super' outside of a class.");
+                } else if self.current_class != ClassType::Subclass {
+                    self.error(keyword, "Can't use 'super' in a class with no superclass.");
+                }
+                *slot = self.resolve_local(keyword);
+            }
+            Expr::This { keyword, slot } => {
+                if self.current_class == ClassType::None {
+                    self.error(keyword, "Can't use 'this' outside of a class.");
+                }
+                *slot = self.resolve_local(keyword);
+            }
+            Expr::Unary { right, .. } => self.resolve_expression(right),
+            Expr::Variable { name, slot } => {
+                if let Some(scope) = self.scopes.last() {
+                    if scope.get(&name.lexeme).is_some_and(|local| !local.defined) {
+                        self.error(name, "Can't read local variable in its own initializer.");
+                    }
+                }
+                *slot = self.resolve_local(name);
+            }
+        }
+    }
+
+    fn resolve_local(&self, name: &Token) -> Option<Slot> {
+        self.scopes
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, scope)| {
+                scope.get(&name.lexeme).map(|local| Slot {
+                    depth,
+                    index: local.index,
+                })
+            })
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            if scope.contains_key(&name.lexeme) {
+                self.error(name, "Already a variable with this name in this scope.");
+                return;
+            }
+            let index = scope.len();
+            scope.insert(
+                name.lexeme.clone(),
+                Local {
+                    defined: false,
+                    index,
+                },
+            );
+        }
+    }
+
+    fn define(&mut self, name: &Token) {
+        self.define_name(&name.lexeme);
+    }
+
+    fn define_name(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            let index = scope.len();
+            scope
+                .entry(name.to_string())
+                .or_insert(Local {
+                    defined: false,
+                    index,
+                })
+                .defined = true;
+        }
+    }
+
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(ResolveError {
+            token: token.clone(),
+            message: message.to_string(),
+        });
+    }
+}
@@ -1,4 +1,6 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::Range};
+
+use thiserror::Error;
 
 use crate::{
     token::{Token, Value},
@@ -8,9 +10,13 @@ use crate::{
 fn build_keyword_map() -> HashMap<String, TokenType> {
     let mut keywords = HashMap::new();
     keywords.insert("and".to_string(), TokenType::And);
+    keywords.insert("break".to_string(), TokenType::Break);
+    keywords.insert("catch".to_string(), TokenType::Catch);
     keywords.insert("class".to_string(), TokenType::Class);
+    keywords.insert("continue".to_string(), TokenType::Continue);
     keywords.insert("else".to_string(), TokenType::Else);
     keywords.insert("false".to_string(), TokenType::False);
+    keywords.insert("finally".to_string(), TokenType::Finally);
     keywords.insert("for".to_string(), TokenType::For);
     keywords.insert("fun".to_string(), TokenType::Fun);
     keywords.insert("if".to_string(), TokenType::If);
@@ -20,19 +26,49 @@ fn build_keyword_map() -> HashMap<String, TokenType> {
     keywords.insert("return".to_string(), TokenType::Return);
     keywords.insert("super".to_string(), TokenType::Super);
     keywords.insert("this".to_string(), TokenType::This);
+    keywords.insert("throw".to_string(), TokenType::Throw);
     keywords.insert("true".to_string(), TokenType::True);
+    keywords.insert("try".to_string(), TokenType::Try);
     keywords.insert("var".to_string(), TokenType::Var);
     keywords.insert("while".to_string(), TokenType::While);
     keywords
 }
 
+/// A lexical error: an unexpected character, an unterminated string or an invalid escape
+/// sequence.
+#[derive(Error, Debug)]
+#[error("[line {line}] Error: {message}")]
+pub struct ScanError {
+    pub line: usize,
+    pub column: usize,
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+/// The quoting of the string literal being scanned, so that scanning can resume in the right
+/// kind of literal after an interpolation.
+#[derive(Clone, Copy, PartialEq)]
+enum Quotes {
+    Single,
+    Triple,
+}
+
 pub struct Scanner {
     pub source: String,
     pub tokens: Vec<Token>,
+    errors: Vec<ScanError>,
     start: usize,
     current: usize,
     line: usize,
+    /// Column of `current`, counted in characters from 1. Kept up to date as characters are
+    /// consumed, as recounting from the start of the line would make long lines quadratic.
+    column: usize,
+    start_line: usize,
+    start_column: usize,
     keyword_map: HashMap<String, TokenType>,
+    /// One entry per `${` currently open: the quoting of the string it interpolates into, and
+    /// the number of braces opened inside it so the `}` that closes it can be told apart.
+    interpolations: Vec<(Quotes, usize)>,
 }
 
 impl Scanner {
@@ -40,16 +76,25 @@ impl Scanner {
         Self {
             source: source.to_string(),
             tokens: vec![],
+            errors: vec![],
             start: 0,
             current: 0,
             line: 1,
+            column: 1,
+            start_line: 1,
+            start_column: 1,
             keyword_map: build_keyword_map(),
+            interpolations: vec![],
         }
     }
 
-    pub fn scan_tokens(&mut self) -> Vec<Token> {
+    /// Scans the whole source. Scanning carries on past lexical errors, so the tokens are always
+    /// complete up to the final `Eof`, and every error is reported at once.
+    pub fn scan_tokens(&mut self) -> (Vec<Token>, Vec<ScanError>) {
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.column;
             self.scan_token();
         }
 
@@ -58,8 +103,10 @@ impl Scanner {
             lexeme: "".to_string(),
             literal: Value::None,
             line: self.line,
+            column: self.column,
+            span: self.current..self.current,
         });
-        self.tokens.clone()
+        (self.tokens.clone(), std::mem::take(&mut self.errors))
     }
 
     fn is_at_end(&self) -> bool {
@@ -71,8 +118,27 @@ impl Scanner {
         match c {
             '(' => self.add_token(TokenType::LeftParen),
             ')' => self.add_token(TokenType::RightParen),
-            '{' => self.add_token(TokenType::LeftBrace),
-            '}' => self.add_token(TokenType::RightBrace),
+            '{' => {
+                if let Some((_, depth)) = self.interpolations.last_mut() {
+                    *depth += 1;
+                }
+                self.add_token(TokenType::LeftBrace)
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some((quotes, 0)) => {
+                    let quotes = *quotes;
+                    self.interpolations.pop();
+                    self.string(quotes);
+                }
+                Some((_, depth)) => {
+                    *depth -= 1;
+                    self.add_token(TokenType::RightBrace)
+                }
+                None => self.add_token(TokenType::RightBrace),
+            },
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
+            ':' => self.add_token(TokenType::Colon),
             ',' => self.add_token(TokenType::Comma),
             '.' => self.add_token(TokenType::Dot),
             '-' => self.add_token(TokenType::Minus),
@@ -88,12 +154,14 @@ impl Scanner {
                 })
             }
             '=' => {
-                let matched = self.match_char('=');
-                self.add_token(if matched {
+                let token_type = if self.match_char('=') {
                     TokenType::EqualEqual
+                } else if self.match_char('>') {
+                    TokenType::Arrow
                 } else {
                     TokenType::Equal
-                })
+                };
+                self.add_token(token_type)
             }
             '<' => {
                 let matched = self.match_char('=');
@@ -123,27 +191,36 @@ impl Scanner {
             ' ' => (),
             '\r' => (),
             '\t' => (),
-            '\n' => self.line += 1,
+            '\n' => self.new_line(),
 
-            '"' => self.string(),
+            '"' => {
+                if self.source[self.current..].starts_with("\"\"") {
+                    self.advance();
+                    self.advance();
+                    self.string(Quotes::Triple);
+                } else {
+                    self.string(Quotes::Single);
+                }
+            }
+            'r' if self.peek() == '"' => self.raw_string(),
 
             _ => {
-                if c.is_digit(10) {
+                if c.is_ascii_digit() {
                     self.number();
-                } else if c.is_alphabetic() {
+                } else if c.is_alphabetic() || c == '_' {
                     self.identifier();
+                } else {
+                    self.error("Unexpected character.");
                 }
             }
         }
     }
 
     fn match_char(&mut self, expected: char) -> bool {
-        if self.is_at_end() {
-            false
-        } else if self.current_char() != expected {
+        if self.is_at_end() || self.current_char() != expected {
             false
         } else {
-            self.current += 1;
+            self.advance();
             true
         }
     }
@@ -157,23 +234,25 @@ impl Scanner {
     }
 
     fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source.len() {
-            '\0'
-        } else {
-            self.source.chars().nth(self.current + 1).unwrap()
-        }
+        self.source[self.current..].chars().nth(1).unwrap_or('\0')
     }
 
     fn current_char(&self) -> char {
-        self.source.chars().nth(self.current).unwrap()
+        self.source[self.current..].chars().next().unwrap()
     }
 
     fn advance(&mut self) -> char {
         let ch = self.current_char();
-        self.current += 1;
+        self.current += ch.len_utf8();
+        self.column += 1;
         ch
     }
 
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.column = 1;
+    }
+
     fn add_token(&mut self, token_type: TokenType) {
         self.add_token_with_value(token_type, Value::None);
     }
@@ -184,36 +263,180 @@ impl Scanner {
             token_type,
             lexeme: text.to_string(),
             literal: value,
-            line: self.line,
+            line: self.start_line,
+            column: self.start_column,
+            span: self.start..self.current,
         });
     }
 
-    fn string(&mut self) {
-        while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+    /// Scans the rest of a string literal, or of a string segment after an interpolation's `}`.
+    /// Triple-quoted strings may contain unescaped `"`.
+    fn string(&mut self, quotes: Quotes) {
+        let terminator = match quotes {
+            Quotes::Single => "\"",
+            Quotes::Triple => "\"\"\"",
+        };
+
+        let mut value = String::new();
+        while !self.source[self.current..].starts_with(terminator) && !self.is_at_end() {
+            match self.advance() {
+                '\n' => {
+                    self.new_line();
+                    value.push('\n');
+                }
+                '\\' => {
+                    if let Some(c) = self.escape() {
+                        value.push(c);
+                    }
+                }
+                '$' if self.peek() == '{' && self.peek_next() == '}' => {
+                    let start = self.current - 1;
+                    self.advance();
+                    self.advance();
+                    self.error_from(start, "Expect expression in interpolation.");
+                }
+                '$' if self.peek() == '{' => {
+                    self.advance();
+                    self.add_token_with_value(
+                        TokenType::Interpolation,
+                        Value::String(value.into()),
+                    );
+                    self.interpolations.push((quotes, 0));
+                    return;
+                }
+                c => value.push(c),
             }
+        }
+
+        if self.is_at_end() {
+            self.error("Unterminated string.");
+            return;
+        }
+
+        for _ in terminator.chars() {
             self.advance();
         }
+        self.add_token_with_value(TokenType::String, Value::String(value.into()));
+    }
+
+    /// Scans a raw string, `r"..."`, in which backslashes and `${` have no special meaning.
+    fn raw_string(&mut self) {
+        self.advance();
+        while self.peek() != '"' && !self.is_at_end() {
+            if self.advance() == '\n' {
+                self.new_line();
+            }
+        }
 
         if self.is_at_end() {
+            self.error("Unterminated string.");
             return;
         }
 
         self.advance();
-        let value = &self.source[self.start + 1..self.current - 1];
-        self.add_token_with_value(TokenType::String, Value::String(value.to_string()));
+        let value = &self.source[self.start + 2..self.current - 1];
+        self.add_token_with_value(TokenType::String, Value::String(value.into()));
+    }
+
+    /// Decodes the escape sequence after a backslash, reporting an error and returning `None` if
+    /// it is invalid.
+    fn escape(&mut self) -> Option<char> {
+        let start = self.current - 1;
+        if self.is_at_end() {
+            return None;
+        }
+
+        let c = match self.advance() {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            '0' => '\0',
+            '"' => '"',
+            '\\' => '\\',
+            '$' => '$',
+            'x' => {
+                let digits = self.hex_digits(2);
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(byte) if digits.len() == 2 && byte.is_ascii() => byte as char,
+                    Ok(_) if digits.len() == 2 => {
+                        return self.error_from(start, "Hex escape must be at most \\x7F.")
+                    }
+                    _ => {
+                        return self.error_from(start, "Hex escape must have two digits.");
+                    }
+                }
+            }
+            'u' => {
+                if !self.match_char('{') {
+                    return self.error_from(start, "Unicode escape must be written as \\u{...}.");
+                }
+                let digits = self.hex_digits(6);
+                if digits.is_empty() || !self.match_char('}') {
+                    return self.error_from(start, "Unicode escape must be written as \\u{...}.");
+                }
+                match u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                {
+                    Some(c) => c,
+                    None => {
+                        let message = format!("Invalid unicode code point '{}'.", digits);
+                        return self.error_from(start, &message);
+                    }
+                }
+            }
+            c => {
+                let message = format!("Invalid escape sequence '\\{}'.", c.escape_default());
+                self.error_from(start, &message);
+                if c == '\n' {
+                    self.new_line();
+                }
+                return None;
+            }
+        };
+        Some(c)
+    }
+
+    /// Consumes up to `max` hexadecimal digits.
+    fn hex_digits(&mut self, max: usize) -> String {
+        let mut digits = String::new();
+        while digits.len() < max && self.peek().is_ascii_hexdigit() {
+            digits.push(self.advance());
+        }
+        digits
+    }
+
+    /// Reports an error covering the token being scanned.
+    fn error(&mut self, message: &str) {
+        self.errors.push(ScanError {
+            line: self.start_line,
+            column: self.start_column,
+            span: self.start..self.current,
+            message: message.to_string(),
+        });
+    }
+
+    /// Reports an error spanning from `start` to the current character, which must be on the
+    /// same line, as is the case for escapes and interpolations.
+    fn error_from(&mut self, start: usize, message: &str) -> Option<char> {
+        self.errors.push(ScanError {
+            line: self.line,
+            column: self.column - self.source[start..self.current].chars().count(),
+            span: start..self.current,
+            message: message.to_string(),
+        });
+        None
     }
 
     fn number(&mut self) {
-        while self.peek().is_digit(10) {
+        while self.peek().is_ascii_digit() {
             self.advance();
         }
 
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance();
 
-            while self.peek().is_digit(10) {
+            while self.peek().is_ascii_digit() {
                 self.advance();
             }
         }
@@ -221,7 +444,7 @@ impl Scanner {
         self.add_token_with_value(
             TokenType::Number,
             Value::Number(
-                *&self.source[self.start..self.current]
+                self.source[self.start..self.current]
                     .parse::<f64>()
                     .unwrap(),
             ),
@@ -229,7 +452,7 @@ impl Scanner {
     }
 
     fn identifier(&mut self) {
-        while self.peek().is_alphanumeric() {
+        while self.peek().is_alphanumeric() || self.peek() == '_' {
             self.advance();
         }
 
@@ -0,0 +1,40 @@
+/// Native stack kept free below a [`StackLimit`], for the work done between two checks and for
+/// unwinding. Debug builds use several times more stack per level of recursion.
+const RED_ZONE: usize = if cfg!(debug_assertions) {
+    256 * 1024
+} else {
+    64 * 1024
+};
+
+/// A point on the current thread's native stack that recursion shouldn't pass. The parser, the
+/// bytecode compiler and the tree-walker all recurse as deeply as the code they handle nests,
+/// and check this to fail cleanly rather than overflow the stack.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StackLimit(usize);
+
+impl StackLimit {
+    /// The limit for the thread this is called on, or no limit if its stack can't be found.
+    pub fn current() -> Self {
+        match stacker::remaining_stack() {
+            Some(remaining) => {
+                let end = position().saturating_sub(remaining);
+                Self(end.saturating_add(RED_ZONE))
+            }
+            None => Self(0),
+        }
+    }
+
+    /// Whether the stack has grown past the limit.
+    #[inline(always)]
+    pub fn exceeded(self) -> bool {
+        position() < self.0
+    }
+}
+
+/// An address in the current native stack frame. The stack grows down, so it shrinks as calls
+/// nest.
+#[inline(always)]
+fn position() -> usize {
+    let marker = 0u8;
+    std::ptr::addr_of!(marker) as usize
+}
@@ -1,3 +1,5 @@
+use std::rc::Rc;
+
 use crate::{expr::Expr, token::Token};
 
 #[derive(Clone)]
@@ -5,13 +7,25 @@ pub enum Stmt {
     Block {
         statements: Vec<Stmt>,
     },
+    Break {
+        keyword: Token,
+    },
+    Class {
+        name: Token,
+        superclass: Option<Expr>,
+        methods: Vec<Stmt>,
+    },
+    Continue {
+        keyword: Token,
+    },
     Expression {
         expression: Expr,
     },
+    /// A function declaration. The body is shared with every function value made from it.
     Function {
         name: Token,
         params: Vec<Token>,
-        body: Vec<Stmt>,
+        body: Rc<[Stmt]>,
     },
     If {
         condition: Expr,
@@ -25,12 +39,32 @@ pub enum Stmt {
         keyword: Token,
         value: Option<Expr>,
     },
+    Throw {
+        keyword: Token,
+        value: Expr,
+    },
+    /// A `try` statement. The parser guarantees at least one of `catch` and `finally`.
+    Try {
+        body: Vec<Stmt>,
+        catch: Option<Catch>,
+        finally: Option<Vec<Stmt>>,
+    },
+    /// A `while` loop, or a desugared `for` loop with its increment kept apart from the body so
+    /// that `continue` still runs it.
     While {
         condition: Expr,
         body: Box<Stmt>,
+        increment: Option<Expr>,
     },
     Var {
         name: Token,
         initializer: Option<Expr>,
     },
 }
+
+/// The `catch (name) { ... }` clause of a `try` statement.
+#[derive(Clone)]
+pub struct Catch {
+    pub name: Token,
+    pub body: Vec<Stmt>,
+}
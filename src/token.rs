@@ -1,12 +1,18 @@
-use std::fmt::Display;
+use std::{fmt::Display, ops::Range};
 
-use crate::{lox_callable::LoxCallable, token_type::TokenType};
+use crate::{
+    lox_callable::LoxCallable, lox_instance::LoxInstance, lox_list::LoxList, lox_map::LoxMap,
+    lox_string::LoxString, token_type::TokenType,
+};
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum Value {
-    String(String),
+    String(LoxString),
     Number(f64),
     Callable(LoxCallable),
+    Instance(LoxInstance),
+    List(LoxList),
+    Map(LoxMap),
     True,
     False,
     None,
@@ -34,14 +40,57 @@ impl Value {
             self == other
         }
     }
+
+    /// If nothing else shares what this value holds, such as a list's elements or the scope a
+    /// closure captured, moves those values into `pending` so that dropping this one doesn't
+    /// have to drop them too.
+    pub(crate) fn take_contents(&mut self, pending: &mut Vec<Value>) {
+        match self {
+            Value::Callable(callable) => callable.take_contents(pending),
+            Value::Instance(instance) => instance.take_contents(pending),
+            Value::List(list) => list.take_contents(pending),
+            Value::Map(map) => map.take_contents(pending),
+            Value::String(_) | Value::Number(_) | Value::True | Value::False | Value::None => (),
+        }
+    }
+
+    /// Whether dropping this value could drop other values along with it.
+    pub(crate) fn holds_values(&self) -> bool {
+        match self {
+            Value::Callable(LoxCallable::BuiltIn(_)) => false,
+            Value::Callable(_) | Value::Instance(_) | Value::List(_) | Value::Map(_) => true,
+            Value::String(_) | Value::Number(_) | Value::True | Value::False | Value::None => false,
+        }
+    }
+
+    /// Drops `values` one at a time, emptying each before it goes. Dropping a value normally
+    /// drops everything it holds recursively, which for a long chain of closures, instances or
+    /// lists would overflow the native stack.
+    pub(crate) fn drop_all(mut values: Vec<Value>) {
+        while let Some(mut value) = values.pop() {
+            value.take_contents(&mut values);
+        }
+    }
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Debug)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub literal: Value,
     pub line: usize,
+    pub column: usize,
+    pub span: Range<usize>,
+}
+
+impl Token {
+    pub fn location(&self) -> String {
+        if self.token_type == TokenType::Eof {
+            " at end".to_string()
+        } else {
+            format!(" at '{}'", self.lexeme)
+        }
+    }
 }
 
 impl Display for Value {
@@ -52,7 +101,15 @@ impl Display for Value {
             Value::None => f.write_str("nil"),
             Value::True => f.write_str("true"),
             Value::False => f.write_str("false"),
-            Value::Callable(_) => f.write_str("function"),
+            Value::Callable(LoxCallable::Class(class)) => f.write_str(&class.name),
+            Value::Callable(LoxCallable::LoxFunction(function)) => match &function.name {
+                Some(name) => write!(f, "<fn {}>", name),
+                None => f.write_str("<fn anonymous>"),
+            },
+            Value::Callable(LoxCallable::BuiltIn(_)) => f.write_str("<native fn>"),
+            Value::Instance(instance) => write!(f, "{} instance", instance.class.name),
+            Value::List(list) => write!(f, "{}", list),
+            Value::Map(map) => write!(f, "{}", map),
         }
     }
 }
@@ -4,6 +4,9 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
@@ -16,6 +19,7 @@ pub enum TokenType {
     BangEqual,
     Equal,
     EqualEqual,
+    Arrow,
     Greater,
     GreaterEqual,
     Less,
@@ -23,12 +27,19 @@ pub enum TokenType {
 
     Identifier,
     String,
+    /// A string segment ending in `${`. The interpolated expression's tokens follow it, and the
+    /// string resumes after the matching `}` as another `Interpolation` or a closing `String`.
+    Interpolation,
     Number,
 
     And,
+    Break,
+    Catch,
     Class,
+    Continue,
     Else,
     False,
+    Finally,
     Fun,
     For,
     If,
@@ -38,7 +49,9 @@ pub enum TokenType {
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
 
@@ -0,0 +1,276 @@
+use std::{fmt::Write, ops::Range};
+
+use super::value::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum OpCode {
+    Constant,
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal,
+    SetLocal,
+    GetGlobal,
+    DefineGlobal,
+    SetGlobal,
+    GetUpvalue,
+    SetUpvalue,
+    GetProperty,
+    SetProperty,
+    GetSuper,
+    Equal,
+    Greater,
+    Less,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    Jump,
+    JumpIfFalse,
+    Loop,
+    Call,
+    Invoke,
+    SuperInvoke,
+    Closure,
+    CloseUpvalue,
+    Return,
+    Class,
+    Inherit,
+    Method,
+    BuildList,
+    BuildMap,
+    MapEntry,
+    BuildString,
+    GetIndex,
+    SetIndex,
+    Throw,
+    PushCatch,
+    PushFinally,
+    PopHandler,
+    EndFinally,
+    /// Prefixes an instruction whose constant index doesn't fit in a byte, widening its
+    /// operand to two bytes.
+    Wide,
+}
+
+impl From<u8> for OpCode {
+    fn from(byte: u8) -> Self {
+        match byte {
+            0 => OpCode::Constant,
+            1 => OpCode::Nil,
+            2 => OpCode::True,
+            3 => OpCode::False,
+            4 => OpCode::Pop,
+            5 => OpCode::GetLocal,
+            6 => OpCode::SetLocal,
+            7 => OpCode::GetGlobal,
+            8 => OpCode::DefineGlobal,
+            9 => OpCode::SetGlobal,
+            10 => OpCode::GetUpvalue,
+            11 => OpCode::SetUpvalue,
+            12 => OpCode::GetProperty,
+            13 => OpCode::SetProperty,
+            14 => OpCode::GetSuper,
+            15 => OpCode::Equal,
+            16 => OpCode::Greater,
+            17 => OpCode::Less,
+            18 => OpCode::Add,
+            19 => OpCode::Subtract,
+            20 => OpCode::Multiply,
+            21 => OpCode::Divide,
+            22 => OpCode::Not,
+            23 => OpCode::Negate,
+            24 => OpCode::Print,
+            25 => OpCode::Jump,
+            26 => OpCode::JumpIfFalse,
+            27 => OpCode::Loop,
+            28 => OpCode::Call,
+            29 => OpCode::Invoke,
+            30 => OpCode::SuperInvoke,
+            31 => OpCode::Closure,
+            32 => OpCode::CloseUpvalue,
+            33 => OpCode::Return,
+            34 => OpCode::Class,
+            35 => OpCode::Inherit,
+            36 => OpCode::Method,
+            37 => OpCode::BuildList,
+            38 => OpCode::BuildMap,
+            39 => OpCode::MapEntry,
+            40 => OpCode::BuildString,
+            41 => OpCode::GetIndex,
+            42 => OpCode::SetIndex,
+            43 => OpCode::Throw,
+            44 => OpCode::PushCatch,
+            45 => OpCode::PushFinally,
+            46 => OpCode::PopHandler,
+            47 => OpCode::EndFinally,
+            48 => OpCode::Wide,
+            _ => panic!("Unknown opcode {}", byte),
+        }
+    }
+}
+
+/// A sequence of bytecode together with its constant pool. Every byte of `code` has a matching
+/// entry in `lines` and `spans` recording where in the source it came from.
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Value>,
+    pub lines: Vec<usize>,
+    pub spans: Vec<Range<usize>>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write(&mut self, byte: u8, line: usize, span: Range<usize>) {
+        self.code.push(byte);
+        self.lines.push(line);
+        self.spans.push(span);
+    }
+
+    pub fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    /// The two-byte operand starting at `offset`.
+    fn read_short(&self, offset: usize) -> u16 {
+        u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+    }
+
+    pub fn disassemble(&self, name: &str) -> String {
+        let mut output = format!("== {} ==\n", name);
+        let mut offset = 0;
+        while offset < self.code.len() {
+            offset = self.disassemble_instruction(&mut output, offset);
+        }
+        output
+    }
+
+    fn disassemble_instruction(&self, output: &mut String, offset: usize) -> usize {
+        let _ = write!(output, "{:04} ", offset);
+        if offset > 0 && self.lines[offset] == self.lines[offset - 1] {
+            output.push_str("   | ");
+        } else {
+            let _ = write!(output, "{:4} ", self.lines[offset]);
+        }
+
+        // A `Wide` prefix is listed as part of the instruction it widens.
+        let wide = OpCode::from(self.code[offset]) == OpCode::Wide;
+        let offset = offset + wide as usize;
+        let instruction = OpCode::from(self.code[offset]);
+        let name = if wide {
+            format!("Wide{:?}", instruction)
+        } else {
+            format!("{:?}", instruction)
+        };
+        // The index of the constant an instruction refers to, and where its operands end.
+        let constant_operand = || {
+            if wide {
+                (self.read_short(offset + 1) as usize, offset + 3)
+            } else {
+                (self.code[offset + 1] as usize, offset + 2)
+            }
+        };
+        match instruction {
+            OpCode::Constant
+            | OpCode::GetGlobal
+            | OpCode::DefineGlobal
+            | OpCode::SetGlobal
+            | OpCode::GetProperty
+            | OpCode::SetProperty
+            | OpCode::GetSuper
+            | OpCode::Class
+            | OpCode::Method => {
+                let (constant, next) = constant_operand();
+                let _ = writeln!(
+                    output,
+                    "{:<16} {:4} '{}'",
+                    name, constant, self.constants[constant]
+                );
+                next
+            }
+            OpCode::GetLocal
+            | OpCode::SetLocal
+            | OpCode::GetUpvalue
+            | OpCode::SetUpvalue
+            | OpCode::Call => {
+                let _ = writeln!(output, "{:<16} {:4}", name, self.code[offset + 1]);
+                offset + 2
+            }
+            OpCode::BuildList | OpCode::BuildString => {
+                let _ = writeln!(output, "{:<16} {:4}", name, self.read_short(offset + 1));
+                offset + 3
+            }
+            OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop | OpCode::PushCatch => {
+                let jump = self.read_short(offset + 1);
+                let target = if instruction == OpCode::Loop {
+                    offset + 3 - jump as usize
+                } else {
+                    offset + 3 + jump as usize
+                };
+                let _ = writeln!(output, "{:<16} {:4} -> {}", name, offset, target);
+                offset + 3
+            }
+            OpCode::PushFinally => {
+                let jump = self.read_short(offset + 1);
+                let target = offset + 3 + jump as usize;
+                let _ = writeln!(
+                    output,
+                    "{:<16} {:4} -> {} (slot {})",
+                    name,
+                    offset,
+                    target,
+                    self.code[offset + 3]
+                );
+                offset + 4
+            }
+            OpCode::Invoke | OpCode::SuperInvoke => {
+                let (constant, next) = constant_operand();
+                let _ = writeln!(
+                    output,
+                    "{:<16} ({} args) {:4} '{}'",
+                    name, self.code[next], constant, self.constants[constant]
+                );
+                next + 1
+            }
+            OpCode::Closure => {
+                let (constant, next) = constant_operand();
+                let function = &self.constants[constant];
+                let _ = writeln!(output, "{:<16} {:4} {}", name, constant, function);
+
+                let mut offset = next;
+                if let Value::Function(function) = function {
+                    for _ in 0..function.upvalue_count {
+                        let kind = if self.code[offset] == 1 {
+                            "local"
+                        } else {
+                            "upvalue"
+                        };
+                        let _ = writeln!(
+                            output,
+                            "{:04}    |                     {} {}",
+                            offset,
+                            kind,
+                            self.code[offset + 1]
+                        );
+                        offset += 2;
+                    }
+                }
+                offset
+            }
+            _ => {
+                let _ = writeln!(output, "{}", name);
+                offset + 1
+            }
+        }
+    }
+}
@@ -0,0 +1,1655 @@
+use std::{collections::HashMap, ops::Range, rc::Rc};
+
+use crate::{
+    parser::{ParseError, MAX_NESTING},
+    stack::StackLimit,
+    token::{self, Token},
+    token_type::TokenType,
+};
+
+use super::{
+    chunk::{Chunk, OpCode},
+    value::{Function, Value},
+};
+
+const MAX_LOCALS: usize = 256;
+const MAX_UPVALUES: usize = 256;
+/// The most elements, entries or parts a list, map or interpolated string can be built from.
+const MAX_LITERAL_PARTS: usize = u16::MAX as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Precedence {
+    None,
+    Assignment,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Call,
+    Primary,
+}
+
+impl Precedence {
+    fn next(self) -> Self {
+        match self {
+            Precedence::None => Precedence::Assignment,
+            Precedence::Assignment => Precedence::Or,
+            Precedence::Or => Precedence::And,
+            Precedence::And => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Unary,
+            Precedence::Unary => Precedence::Call,
+            Precedence::Call | Precedence::Primary => Precedence::Primary,
+        }
+    }
+}
+
+type ParseFn = fn(&mut Compiler, bool);
+
+struct ParseRule {
+    prefix: Option<ParseFn>,
+    infix: Option<ParseFn>,
+    precedence: Precedence,
+}
+
+impl ParseRule {
+    fn new(prefix: Option<ParseFn>, infix: Option<ParseFn>, precedence: Precedence) -> Self {
+        Self {
+            prefix,
+            infix,
+            precedence,
+        }
+    }
+}
+
+fn rule(token_type: TokenType) -> ParseRule {
+    match token_type {
+        TokenType::LeftParen => ParseRule::new(
+            Some(Compiler::grouping),
+            Some(Compiler::call),
+            Precedence::Call,
+        ),
+        TokenType::Dot => ParseRule::new(None, Some(Compiler::dot), Precedence::Call),
+        TokenType::LeftBrace => ParseRule::new(Some(Compiler::map), None, Precedence::None),
+        TokenType::LeftBracket => ParseRule::new(
+            Some(Compiler::list),
+            Some(Compiler::index),
+            Precedence::Call,
+        ),
+        TokenType::Minus => ParseRule::new(
+            Some(Compiler::unary),
+            Some(Compiler::binary),
+            Precedence::Term,
+        ),
+        TokenType::Plus => ParseRule::new(None, Some(Compiler::binary), Precedence::Term),
+        TokenType::Slash | TokenType::Star => {
+            ParseRule::new(None, Some(Compiler::binary), Precedence::Factor)
+        }
+        TokenType::Bang => ParseRule::new(Some(Compiler::unary), None, Precedence::None),
+        TokenType::BangEqual | TokenType::EqualEqual => {
+            ParseRule::new(None, Some(Compiler::binary), Precedence::Equality)
+        }
+        TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual => {
+            ParseRule::new(None, Some(Compiler::binary), Precedence::Comparison)
+        }
+        TokenType::Identifier => ParseRule::new(Some(Compiler::variable), None, Precedence::None),
+        TokenType::Fun => {
+            ParseRule::new(Some(Compiler::function_expression), None, Precedence::None)
+        }
+        TokenType::String => ParseRule::new(Some(Compiler::string), None, Precedence::None),
+        TokenType::Interpolation => {
+            ParseRule::new(Some(Compiler::interpolation), None, Precedence::None)
+        }
+        TokenType::Number => ParseRule::new(Some(Compiler::number), None, Precedence::None),
+        TokenType::And => ParseRule::new(None, Some(Compiler::and), Precedence::And),
+        TokenType::Or => ParseRule::new(None, Some(Compiler::or), Precedence::Or),
+        TokenType::False | TokenType::True | TokenType::Nil => {
+            ParseRule::new(Some(Compiler::literal), None, Precedence::None)
+        }
+        TokenType::Super => ParseRule::new(Some(Compiler::super_), None, Precedence::None),
+        TokenType::This => ParseRule::new(Some(Compiler::this), None, Precedence::None),
+        _ => ParseRule::new(None, None, Precedence::None),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Function,
+    Initializer,
+    Method,
+    Script,
+}
+
+struct Local {
+    name: String,
+    /// `None` while the variable's initializer is being compiled.
+    depth: Option<usize>,
+    is_captured: bool,
+}
+
+struct UpvalueRef {
+    index: u8,
+    is_local: bool,
+}
+
+/// An enclosing loop that `break` and `continue` can target.
+struct Loop {
+    /// Where `continue` jumps back to: the increment of a `for`, otherwise the condition.
+    start: usize,
+    /// The scope depth outside the loop body. Locals deeper than this are discarded on leaving.
+    scope_depth: usize,
+    /// `break` jumps to patch once the end of the loop is known.
+    break_jumps: Vec<usize>,
+    /// The number of `try` statements the loop is inside. Any beyond these are inside the loop.
+    try_depth: usize,
+}
+
+/// The part of a `try` statement being compiled, which decides what leaving it early involves.
+#[derive(Clone, Copy, PartialEq)]
+enum TryRegion {
+    Body,
+    Catch,
+    Finally,
+}
+
+/// A way of leaving a statement early. An exit that crosses a `finally` block records itself in
+/// the statement's completion slots and runs the block, after which it carries on.
+#[derive(Clone, Copy, PartialEq)]
+enum Exit {
+    Break,
+    Continue,
+    Return,
+}
+
+/// An enclosing `try` statement.
+struct Try {
+    region: TryRegion,
+    /// For a statement with a `finally` block, the slot holding its completion value, followed
+    /// by the slot holding its completion kind: `nil` when the protected code finished normally,
+    /// an [`Exit`] number, or the error being unwound.
+    completion: Option<u8>,
+    /// The scope depth of the completion slots.
+    scope_depth: usize,
+    /// Exits routed through the `finally` block, and their jumps to it to patch.
+    exits: Vec<Exit>,
+    finally_jumps: Vec<usize>,
+}
+
+/// A number or string constant, as the key that finds its existing slot in a chunk's constants.
+/// Numbers are compared bit for bit, so `0` and `-0` stay distinct.
+#[derive(PartialEq, Eq, Hash)]
+enum ConstantKey {
+    Number(u64),
+    String(Rc<str>),
+}
+
+struct FunctionState {
+    function: Function,
+    kind: FunctionKind,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueRef>,
+    scope_depth: usize,
+    loops: Vec<Loop>,
+    tries: Vec<Try>,
+    /// Where each number and string constant already in the chunk is, so that repeats share it.
+    constants: HashMap<ConstantKey, u16>,
+}
+
+impl FunctionState {
+    fn new(kind: FunctionKind, name: Option<String>) -> Self {
+        // Slot zero holds the callee itself, or the receiver inside methods.
+        let receiver = match kind {
+            FunctionKind::Initializer | FunctionKind::Method => "this",
+            FunctionKind::Function | FunctionKind::Script => "",
+        };
+        Self {
+            function: Function {
+                name,
+                ..Function::default()
+            },
+            kind,
+            locals: vec![Local {
+                name: receiver.to_string(),
+                depth: Some(0),
+                is_captured: false,
+            }],
+            upvalues: vec![],
+            scope_depth: 0,
+            loops: vec![],
+            tries: vec![],
+            constants: HashMap::new(),
+        }
+    }
+}
+
+struct ClassState {
+    has_superclass: bool,
+}
+
+/// Single-pass compiler from scanner tokens straight to bytecode, reporting the same static
+/// errors as the parser and resolver.
+pub struct Compiler {
+    tokens: Vec<Token>,
+    current: usize,
+    previous: usize,
+    panic_mode: bool,
+    errors: Vec<ParseError>,
+    functions: Vec<FunctionState>,
+    classes: Vec<ClassState>,
+    /// How many declarations and expressions enclose the one being compiled.
+    nesting: usize,
+    stack_limit: StackLimit,
+    /// Source offset where the expression being compiled by the current prefix or infix rule
+    /// starts, so runtime errors can underline all of it like the tree-walker does.
+    expression_start: usize,
+}
+
+impl Compiler {
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Self {
+            tokens,
+            current: 0,
+            previous: 0,
+            panic_mode: false,
+            errors: vec![],
+            functions: vec![FunctionState::new(FunctionKind::Script, None)],
+            classes: vec![],
+            nesting: 0,
+            stack_limit: StackLimit::current(),
+            expression_start: 0,
+        }
+    }
+
+    pub fn compile(mut self) -> Result<Function, Vec<ParseError>> {
+        while !self.do_match(TokenType::Eof) {
+            self.declaration();
+        }
+
+        let (function, _) = self.end_function();
+        if self.errors.is_empty() {
+            Ok(function)
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn declaration(&mut self) {
+        if self.do_match(TokenType::Class) {
+            self.class_declaration();
+        } else if self.check(TokenType::Fun) && !self.check_next(TokenType::LeftParen) {
+            self.advance();
+            self.fun_declaration();
+        } else if self.do_match(TokenType::Var) {
+            self.var_declaration();
+        } else {
+            self.statement();
+        }
+
+        if self.panic_mode {
+            self.synchronize();
+        }
+    }
+
+    fn class_declaration(&mut self) {
+        self.consume(TokenType::Identifier, "Expect class name.");
+        let class_name = self.previous().clone();
+        let name_constant = self.identifier_constant(&class_name.lexeme);
+        self.declare_variable();
+
+        self.emit_constant_op(OpCode::Class, name_constant);
+        self.define_variable(name_constant);
+
+        self.classes.push(ClassState {
+            has_superclass: false,
+        });
+
+        if self.do_match(TokenType::Less) {
+            self.consume(TokenType::Identifier, "Expect superclass name.");
+            self.variable(false);
+
+            if self.previous().lexeme == class_name.lexeme {
+                self.error("A class can't inherit from itself.");
+            }
+
+            self.begin_scope();
+            self.add_local("super");
+            self.define_variable(0);
+
+            self.named_variable(&class_name.lexeme, false);
+            self.emit_op(OpCode::Inherit);
+            if let Some(class) = self.classes.last_mut() {
+                class.has_superclass = true;
+            }
+        }
+
+        self.named_variable(&class_name.lexeme, false);
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.");
+        while !self.check(TokenType::RightBrace) && !self.check(TokenType::Eof) {
+            self.method();
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.");
+        self.emit_op(OpCode::Pop);
+
+        if self.classes.pop().is_some_and(|class| class.has_superclass) {
+            self.end_scope();
+        }
+    }
+
+    fn method(&mut self) {
+        self.nested(|compiler| {
+            compiler.consume(TokenType::Identifier, "Expect method name.");
+            let name = compiler.previous().lexeme.clone();
+            let constant = compiler.identifier_constant(&name);
+            let kind = if name == "init" {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            compiler.function(kind, "method");
+            compiler.emit_constant_op(OpCode::Method, constant);
+        });
+    }
+
+    fn fun_declaration(&mut self) {
+        self.nested(|compiler| {
+            let global = compiler.parse_variable("Expect function name.");
+            compiler.mark_initialized();
+            compiler.function(FunctionKind::Function, "function");
+            compiler.define_variable(global);
+        });
+    }
+
+    fn function(&mut self, kind: FunctionKind, kind_name: &str) {
+        let name = self.previous().lexeme.clone();
+        self.functions.push(FunctionState::new(kind, Some(name)));
+        self.begin_scope();
+
+        self.consume(
+            TokenType::LeftParen,
+            &format!("Expect '(' after {} name.", kind_name),
+        );
+        self.parameters();
+        self.consume(
+            TokenType::LeftBrace,
+            &format!("Expect '{{' before {} body.", kind_name),
+        );
+        self.block();
+        self.end_closure();
+    }
+
+    /// `fun (a) { ... }` in expression position.
+    fn function_expression(&mut self, _can_assign: bool) {
+        self.functions
+            .push(FunctionState::new(FunctionKind::Function, None));
+        self.begin_scope();
+
+        self.consume(TokenType::LeftParen, "Expect '(' after 'fun'.");
+        self.parameters();
+        self.consume(TokenType::LeftBrace, "Expect '{' before function body.");
+        self.block();
+        self.end_closure();
+    }
+
+    /// `(a) => ...`, entered with the opening parenthesis already consumed.
+    fn arrow_function(&mut self) {
+        self.functions
+            .push(FunctionState::new(FunctionKind::Function, None));
+        self.begin_scope();
+
+        self.parameters();
+        self.consume(TokenType::Arrow, "Expect '=>' after parameters.");
+        self.expression();
+        self.emit_op(OpCode::Return);
+        self.end_closure();
+    }
+
+    /// Whether the tokens after an opening parenthesis are an arrow function's parameters,
+    /// `a, b) =>`, rather than a parenthesized expression.
+    fn is_arrow_function(&self) -> bool {
+        let token_type = |offset: usize| {
+            self.tokens
+                .get(self.current + offset)
+                .map(|token| token.token_type)
+        };
+        let mut offset = 0;
+        if token_type(offset) != Some(TokenType::RightParen) {
+            loop {
+                if token_type(offset) != Some(TokenType::Identifier) {
+                    return false;
+                }
+                offset += 1;
+                match token_type(offset) {
+                    Some(TokenType::Comma) => offset += 1,
+                    Some(TokenType::RightParen) => break,
+                    _ => return false,
+                }
+            }
+        }
+        token_type(offset + 1) == Some(TokenType::Arrow)
+    }
+
+    /// Compiles a parameter list up to and including its closing `)`.
+    fn parameters(&mut self) {
+        if !self.check(TokenType::RightParen) {
+            loop {
+                self.current_function().function.arity += 1;
+                if self.current_function().function.arity > 255 {
+                    self.error_at_current("Can't have more than 255 parameters.");
+                }
+                let constant = self.parse_variable("Expect parameter name.");
+                self.define_variable(constant);
+                if !self.do_match(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.");
+    }
+
+    /// Finishes the innermost function and emits the closure that creates it.
+    fn end_closure(&mut self) {
+        let (function, upvalues) = self.end_function();
+        let constant = self.make_constant(Value::Function(Rc::new(function)));
+        self.emit_constant_op(OpCode::Closure, constant);
+        for upvalue in upvalues {
+            self.emit_bytes(upvalue.is_local as u8, upvalue.index);
+        }
+    }
+
+    fn var_declaration(&mut self) {
+        let global = self.parse_variable("Expect variable name.");
+
+        if self.do_match(TokenType::Equal) {
+            self.expression();
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+        self.consume(
+            TokenType::Semicolon,
+            "Expect ';' after variable declaration.",
+        );
+
+        self.define_variable(global);
+    }
+
+    fn statement(&mut self) {
+        self.nested(Self::nested_statement);
+    }
+
+    fn nested_statement(&mut self) {
+        if self.do_match(TokenType::Print) {
+            self.print_statement();
+        } else if self.do_match(TokenType::Break) {
+            self.break_statement();
+        } else if self.do_match(TokenType::Continue) {
+            self.continue_statement();
+        } else if self.do_match(TokenType::For) {
+            self.for_statement();
+        } else if self.do_match(TokenType::If) {
+            self.if_statement();
+        } else if self.do_match(TokenType::Return) {
+            self.return_statement();
+        } else if self.do_match(TokenType::Throw) {
+            self.throw_statement();
+        } else if self.do_match(TokenType::Try) {
+            self.try_statement();
+        } else if self.do_match(TokenType::While) {
+            self.while_statement();
+        } else if self.do_match(TokenType::LeftBrace) {
+            self.begin_scope();
+            self.block();
+            self.end_scope();
+        } else {
+            self.expression_statement();
+        }
+    }
+
+    fn throw_statement(&mut self) {
+        let keyword = self.previous().clone();
+        self.expression();
+        let span = keyword.span.start..self.previous().span.end;
+        self.consume(TokenType::Semicolon, "Expect ';' after thrown value.");
+        self.current_chunk()
+            .write(OpCode::Throw as u8, keyword.line, span);
+    }
+
+    /// Compiles a `try` statement. The body runs under a handler that resumes at the `catch`
+    /// clause, or at the `finally` block if there is no `catch`; the `catch` clause runs under
+    /// one that resumes at the `finally` block. The `finally` block is compiled once and ends by
+    /// carrying on with whatever brought execution to it.
+    fn try_statement(&mut self) {
+        let (has_catch, has_finally) = self.try_clauses();
+        self.begin_scope();
+        let completion = if has_finally {
+            let slot = self.current_function().locals.len() as u8;
+            for _ in 0..2 {
+                self.emit_op(OpCode::Nil);
+                self.add_local("");
+                self.mark_initialized();
+            }
+            Some(slot)
+        } else {
+            None
+        };
+        let scope_depth = self.current_function().scope_depth;
+        self.current_function().tries.push(Try {
+            region: TryRegion::Body,
+            completion,
+            scope_depth,
+            exits: vec![],
+            finally_jumps: vec![],
+        });
+
+        self.consume(TokenType::LeftBrace, "Expect '{' after 'try'.");
+        let body_handler = self.emit_handler(if has_catch { None } else { completion });
+        self.begin_scope();
+        self.block();
+        self.end_scope();
+        self.emit_op(OpCode::PopHandler);
+        let mut finally_jumps = vec![self.emit_jump(OpCode::Jump)];
+
+        if has_catch {
+            self.patch_jump(body_handler);
+        } else {
+            finally_jumps.push(body_handler);
+        }
+        if self.do_match(TokenType::Catch) {
+            self.current_try().region = TryRegion::Catch;
+            self.consume(TokenType::LeftParen, "Expect '(' after 'catch'.");
+            self.consume(TokenType::Identifier, "Expect exception name.");
+            self.begin_scope();
+            self.declare_variable();
+            self.mark_initialized();
+            self.consume(TokenType::RightParen, "Expect ')' after exception name.");
+            self.consume(TokenType::LeftBrace, "Expect '{' before catch body.");
+
+            let catch_handler = completion.map(|slot| self.emit_handler(Some(slot)));
+            self.block();
+            if let Some(catch_handler) = catch_handler {
+                self.emit_op(OpCode::PopHandler);
+                finally_jumps.push(catch_handler);
+            }
+            self.end_scope();
+        }
+
+        match completion {
+            Some(slot) => {
+                self.consume(TokenType::Finally, "Expect 'finally'.");
+                self.consume(TokenType::LeftBrace, "Expect '{' after 'finally'.");
+                let state = self.current_try();
+                state.region = TryRegion::Finally;
+                finally_jumps.append(&mut state.finally_jumps);
+                for jump in finally_jumps {
+                    self.patch_jump(jump);
+                }
+
+                self.begin_scope();
+                self.block();
+                self.end_scope();
+                let exits = self.current_function().tries.pop().map(|state| state.exits);
+                self.end_finally(slot, exits.unwrap_or_default());
+            }
+            None => {
+                self.current_function().tries.pop();
+                for jump in finally_jumps {
+                    self.patch_jump(jump);
+                }
+                if !has_catch {
+                    self.error_at_current("Expect 'catch' or 'finally' after try block.");
+                }
+            }
+        }
+        self.end_scope();
+    }
+
+    /// Looks past the `try` block, whose `{` is the next token, for the clauses that follow it.
+    fn try_clauses(&self) -> (bool, bool) {
+        let token_type = |index: usize| {
+            self.tokens
+                .get(index)
+                .map_or(TokenType::Eof, |token| token.token_type)
+        };
+        let skip_block = |mut index: usize| {
+            let mut depth = 0;
+            loop {
+                match token_type(index) {
+                    TokenType::LeftBrace => depth += 1,
+                    TokenType::RightBrace if depth <= 1 => return index + 1,
+                    TokenType::RightBrace => depth -= 1,
+                    TokenType::Eof => return index,
+                    _ => (),
+                }
+                index += 1;
+            }
+        };
+
+        let mut index = skip_block(self.current);
+        let has_catch = token_type(index) == TokenType::Catch;
+        if has_catch {
+            while !matches!(token_type(index), TokenType::LeftBrace | TokenType::Eof) {
+                index += 1;
+            }
+            index = skip_block(index);
+        }
+        (has_catch, token_type(index) == TokenType::Finally)
+    }
+
+    /// Emits the instruction that installs a handler for the code after it: one resuming at a
+    /// `catch` clause, or with the slot of the completion value, one resuming at a `finally`
+    /// block. Returns the jump to patch with where the handler resumes.
+    fn emit_handler(&mut self, completion: Option<u8>) -> usize {
+        match completion {
+            None => self.emit_jump(OpCode::PushCatch),
+            Some(slot) => {
+                let jump = self.emit_jump(OpCode::PushFinally);
+                self.emit_byte(slot);
+                jump
+            }
+        }
+    }
+
+    /// Carries on after a `finally` block with whatever exit or error was recorded in the
+    /// completion slots before it ran.
+    fn end_finally(&mut self, slot: u8, exits: Vec<Exit>) {
+        for exit in exits {
+            self.emit_bytes(OpCode::GetLocal as u8, slot + 1);
+            self.emit_constant(Value::Number(exit as u8 as f64));
+            self.emit_op(OpCode::Equal);
+            let skip = self.emit_jump(OpCode::JumpIfFalse);
+            self.emit_op(OpCode::Pop);
+            if exit == Exit::Return {
+                self.emit_bytes(OpCode::GetLocal as u8, slot);
+            }
+            self.emit_exit(exit);
+            self.patch_jump(skip);
+            self.emit_op(OpCode::Pop);
+        }
+
+        self.emit_bytes(OpCode::GetLocal as u8, slot);
+        self.emit_bytes(OpCode::GetLocal as u8, slot + 1);
+        self.emit_op(OpCode::EndFinally);
+    }
+
+    fn print_statement(&mut self) {
+        self.expression();
+        self.consume(TokenType::Semicolon, "Expect ';' after value.");
+        self.emit_op(OpCode::Print);
+    }
+
+    fn for_statement(&mut self) {
+        self.begin_scope();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.");
+        if self.do_match(TokenType::Semicolon) {
+            // No initializer.
+        } else if self.do_match(TokenType::Var) {
+            self.var_declaration();
+        } else {
+            self.expression_statement();
+        }
+
+        let mut loop_start = self.current_chunk().code.len();
+        let mut exit_jump = None;
+        if !self.do_match(TokenType::Semicolon) {
+            self.expression();
+            self.consume(TokenType::Semicolon, "Expect ';' after loop condition.");
+
+            exit_jump = Some(self.emit_jump(OpCode::JumpIfFalse));
+            self.emit_op(OpCode::Pop);
+        }
+
+        if !self.do_match(TokenType::RightParen) {
+            let body_jump = self.emit_jump(OpCode::Jump);
+            let increment_start = self.current_chunk().code.len();
+            self.expression();
+            self.emit_op(OpCode::Pop);
+            self.consume(TokenType::RightParen, "Expect ')' after for clauses.");
+
+            self.emit_loop(loop_start);
+            loop_start = increment_start;
+            self.patch_jump(body_jump);
+        }
+
+        self.begin_loop(loop_start);
+        self.statement();
+        self.emit_loop(loop_start);
+
+        if let Some(exit_jump) = exit_jump {
+            self.patch_jump(exit_jump);
+            self.emit_op(OpCode::Pop);
+        }
+        self.end_loop();
+        self.end_scope();
+    }
+
+    fn if_statement(&mut self) {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.");
+        self.expression();
+        self.consume(TokenType::RightParen, "Expect ')' after 'if' condition.");
+
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.statement();
+
+        let else_jump = self.emit_jump(OpCode::Jump);
+        self.patch_jump(then_jump);
+        self.emit_op(OpCode::Pop);
+
+        if self.do_match(TokenType::Else) {
+            self.statement();
+        }
+        self.patch_jump(else_jump);
+    }
+
+    fn return_statement(&mut self) {
+        if self.current_function().kind == FunctionKind::Script {
+            self.error("Can't return from top-level code.");
+        }
+
+        if self.do_match(TokenType::Semicolon) {
+            self.emit_return_value();
+        } else {
+            if self.current_function().kind == FunctionKind::Initializer {
+                self.error("Can't return a value from an initializer.");
+            }
+            self.expression();
+            self.consume(TokenType::Semicolon, "Expect ';' after return value.");
+        }
+        self.emit_exit(Exit::Return);
+    }
+
+    fn while_statement(&mut self) {
+        let loop_start = self.current_chunk().code.len();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.");
+        self.expression();
+        self.consume(TokenType::RightParen, "Expect ')' after condition.");
+
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.begin_loop(loop_start);
+        self.statement();
+        self.emit_loop(loop_start);
+
+        self.patch_jump(exit_jump);
+        self.emit_op(OpCode::Pop);
+        self.end_loop();
+    }
+
+    fn break_statement(&mut self) {
+        if self.current_function().loops.is_empty() {
+            self.error("Can't use 'break' outside of a loop.");
+        }
+        self.consume(TokenType::Semicolon, "Expect ';' after 'break'.");
+        self.emit_exit(Exit::Break);
+    }
+
+    fn continue_statement(&mut self) {
+        if self.current_function().loops.is_empty() {
+            self.error("Can't use 'continue' outside of a loop.");
+        }
+        self.consume(TokenType::Semicolon, "Expect ';' after 'continue'.");
+        self.emit_exit(Exit::Continue);
+    }
+
+    /// Leaves the enclosing loop or function, with the return value on top of the stack for a
+    /// `return`. Handlers of the `try` statements left behind are removed, and the first
+    /// `finally` block in the way runs before the exit carries on.
+    fn emit_exit(&mut self, exit: Exit) {
+        let function = self.current_function();
+        let try_depth = match (exit, function.loops.last()) {
+            (Exit::Return, _) => 0,
+            (_, Some(current_loop)) => current_loop.try_depth,
+            (_, None) => return,
+        };
+
+        for index in (try_depth..function.tries.len()).rev() {
+            let state = &self.current_function().tries[index];
+            let (region, completion, scope_depth) =
+                (state.region, state.completion, state.scope_depth);
+            let handler_active = match region {
+                TryRegion::Body => true,
+                TryRegion::Catch => completion.is_some(),
+                TryRegion::Finally => false,
+            };
+            if handler_active {
+                self.emit_op(OpCode::PopHandler);
+            }
+
+            if let (Some(slot), TryRegion::Body | TryRegion::Catch) = (completion, region) {
+                if exit == Exit::Return {
+                    self.emit_bytes(OpCode::SetLocal as u8, slot);
+                    self.emit_op(OpCode::Pop);
+                }
+                self.discard_locals(scope_depth);
+                self.emit_constant(Value::Number(exit as u8 as f64));
+                self.emit_bytes(OpCode::SetLocal as u8, slot + 1);
+                self.emit_op(OpCode::Pop);
+
+                let jump = self.emit_jump(OpCode::Jump);
+                let state = &mut self.current_function().tries[index];
+                state.finally_jumps.push(jump);
+                if !state.exits.contains(&exit) {
+                    state.exits.push(exit);
+                }
+                return;
+            }
+        }
+
+        match exit {
+            Exit::Return => self.emit_op(OpCode::Return),
+            Exit::Break => {
+                if let Some(scope_depth) = self.current_loop_depth() {
+                    self.discard_locals(scope_depth);
+                    let jump = self.emit_jump(OpCode::Jump);
+                    if let Some(current_loop) = self.current_function().loops.last_mut() {
+                        current_loop.break_jumps.push(jump);
+                    }
+                }
+            }
+            Exit::Continue => {
+                if let Some(scope_depth) = self.current_loop_depth() {
+                    self.discard_locals(scope_depth);
+                    let start = self.current_function().loops.last().map_or(0, |l| l.start);
+                    self.emit_loop(start);
+                }
+            }
+        }
+    }
+
+    fn begin_loop(&mut self, start: usize) {
+        let function = self.current_function();
+        let (scope_depth, try_depth) = (function.scope_depth, function.tries.len());
+        function.loops.push(Loop {
+            start,
+            scope_depth,
+            break_jumps: vec![],
+            try_depth,
+        });
+    }
+
+    fn end_loop(&mut self) {
+        if let Some(finished) = self.current_function().loops.pop() {
+            for jump in finished.break_jumps {
+                self.patch_jump(jump);
+            }
+        }
+    }
+
+    fn current_loop_depth(&mut self) -> Option<usize> {
+        self.current_function()
+            .loops
+            .last()
+            .map(|current_loop| current_loop.scope_depth)
+    }
+
+    /// Emits the pops for locals declared inside the loop body when jumping out of it, without
+    /// forgetting them: compilation carries on in their scope after the jump.
+    fn discard_locals(&mut self, scope_depth: usize) {
+        let captured: Vec<bool> = self
+            .current_function()
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth.is_none_or(|depth| depth > scope_depth))
+            .map(|local| local.is_captured)
+            .collect();
+        for is_captured in captured {
+            if is_captured {
+                self.emit_op(OpCode::CloseUpvalue);
+            } else {
+                self.emit_op(OpCode::Pop);
+            }
+        }
+    }
+
+    fn block(&mut self) {
+        while !self.check(TokenType::RightBrace) && !self.check(TokenType::Eof) {
+            self.declaration();
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after block.");
+    }
+
+    fn expression_statement(&mut self) {
+        self.expression();
+        self.consume(TokenType::Semicolon, "Expect ';' after expression.");
+        self.emit_op(OpCode::Pop);
+    }
+
+    fn expression(&mut self) {
+        self.nested(|compiler| compiler.parse_precedence(Precedence::Assignment));
+    }
+
+    fn parse_precedence(&mut self, precedence: Precedence) {
+        self.advance();
+        let start = self.previous().span.start;
+        let prefix = match rule(self.previous().token_type).prefix {
+            Some(prefix) => prefix,
+            None => {
+                self.error("Expect expression.");
+                return;
+            }
+        };
+
+        let can_assign = precedence <= Precedence::Assignment;
+        self.expression_start = start;
+        prefix(self, can_assign);
+
+        while precedence <= rule(self.peek().token_type).precedence {
+            self.advance();
+            if let Some(infix) = rule(self.previous().token_type).infix {
+                self.expression_start = start;
+                infix(self, can_assign);
+            }
+        }
+
+        if can_assign && self.do_match(TokenType::Equal) {
+            self.error("Invalid assignment target.");
+        }
+    }
+
+    fn grouping(&mut self, _can_assign: bool) {
+        if self.is_arrow_function() {
+            self.arrow_function();
+            return;
+        }
+
+        self.expression();
+        self.consume(TokenType::RightParen, "Expect ')' after expression.");
+    }
+
+    fn number(&mut self, _can_assign: bool) {
+        if let token::Value::Number(number) = self.previous().literal {
+            self.emit_constant(Value::Number(number));
+        }
+    }
+
+    fn string(&mut self, _can_assign: bool) {
+        if let token::Value::String(string) = &self.previous().literal {
+            let value = Value::String(Rc::from(string.as_str()));
+            self.emit_constant(value);
+        }
+    }
+
+    /// Pushes each string segment and interpolated value, then joins them with `BuildString`.
+    fn interpolation(&mut self, _can_assign: bool) {
+        let mut part_count: usize = 0;
+        loop {
+            if self.previous().literal != token::Value::String("".into()) {
+                self.string(false);
+                part_count += 1;
+            }
+            self.expression();
+            part_count += 1;
+
+            if !self.do_match(TokenType::Interpolation) {
+                break;
+            }
+        }
+
+        self.consume(TokenType::String, "Expect '}' after interpolation.");
+        if self.previous().literal != token::Value::String("".into()) {
+            self.string(false);
+            part_count += 1;
+        }
+
+        if part_count > MAX_LITERAL_PARTS {
+            self.error("Can't have more than 65535 parts in an interpolated string.");
+        }
+        self.emit_op(OpCode::BuildString);
+        self.emit_short(part_count.min(MAX_LITERAL_PARTS) as u16);
+    }
+
+    fn literal(&mut self, _can_assign: bool) {
+        match self.previous().token_type {
+            TokenType::False => self.emit_op(OpCode::False),
+            TokenType::Nil => self.emit_op(OpCode::Nil),
+            TokenType::True => self.emit_op(OpCode::True),
+            _ => unreachable!(),
+        }
+    }
+
+    fn unary(&mut self, _can_assign: bool) {
+        let operator = self.previous().clone();
+        self.nested(|compiler| compiler.parse_precedence(Precedence::Unary));
+
+        let start = operator.span.start;
+        match operator.token_type {
+            TokenType::Bang => self.emit_op_spanning(OpCode::Not, &operator, start),
+            TokenType::Minus => self.emit_op_spanning(OpCode::Negate, &operator, start),
+            _ => unreachable!(),
+        }
+    }
+
+    fn binary(&mut self, _can_assign: bool) {
+        let start = self.expression_start;
+        let operator = self.previous().clone();
+        self.parse_precedence(rule(operator.token_type).precedence.next());
+
+        match operator.token_type {
+            TokenType::BangEqual => {
+                self.emit_op_spanning(OpCode::Equal, &operator, start);
+                self.emit_op_spanning(OpCode::Not, &operator, start);
+            }
+            TokenType::EqualEqual => self.emit_op_spanning(OpCode::Equal, &operator, start),
+            TokenType::Greater => self.emit_op_spanning(OpCode::Greater, &operator, start),
+            TokenType::GreaterEqual => {
+                self.emit_op_spanning(OpCode::Less, &operator, start);
+                self.emit_op_spanning(OpCode::Not, &operator, start);
+            }
+            TokenType::Less => self.emit_op_spanning(OpCode::Less, &operator, start),
+            TokenType::LessEqual => {
+                self.emit_op_spanning(OpCode::Greater, &operator, start);
+                self.emit_op_spanning(OpCode::Not, &operator, start);
+            }
+            TokenType::Plus => self.emit_op_spanning(OpCode::Add, &operator, start),
+            TokenType::Minus => self.emit_op_spanning(OpCode::Subtract, &operator, start),
+            TokenType::Star => self.emit_op_spanning(OpCode::Multiply, &operator, start),
+            TokenType::Slash => self.emit_op_spanning(OpCode::Divide, &operator, start),
+            _ => unreachable!(),
+        }
+    }
+
+    fn and(&mut self, _can_assign: bool) {
+        let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.parse_precedence(Precedence::And);
+        self.patch_jump(end_jump);
+    }
+
+    fn or(&mut self, _can_assign: bool) {
+        let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+        let end_jump = self.emit_jump(OpCode::Jump);
+
+        self.patch_jump(else_jump);
+        self.emit_op(OpCode::Pop);
+
+        self.parse_precedence(Precedence::Or);
+        self.patch_jump(end_jump);
+    }
+
+    fn call(&mut self, _can_assign: bool) {
+        let start = self.expression_start;
+        let argument_count = self.argument_list();
+        let paren = self.previous().clone();
+        self.emit_op_spanning(OpCode::Call, &paren, start);
+        self.emit_argument_count(argument_count, start);
+    }
+
+    fn dot(&mut self, can_assign: bool) {
+        let start = self.expression_start;
+        self.consume(TokenType::Identifier, "Expect property name after '.'.");
+        let name = self.previous().clone();
+        let constant = self.identifier_constant(&name.lexeme);
+
+        if can_assign && self.do_match(TokenType::Equal) {
+            self.expression();
+            let span = start..self.previous().span.end;
+            self.emit_constant_op_at(OpCode::SetProperty, constant, name.line, span);
+        } else if self.do_match(TokenType::LeftParen) {
+            let argument_count = self.argument_list();
+            self.emit_constant_op_at(OpCode::Invoke, constant, name.line, name.span);
+            self.emit_argument_count(argument_count, start);
+        } else {
+            let span = start..name.span.end;
+            self.emit_constant_op_at(OpCode::GetProperty, constant, name.line, span);
+        }
+    }
+
+    fn list(&mut self, _can_assign: bool) {
+        let mut element_count: usize = 0;
+        if !self.check(TokenType::RightBracket) {
+            loop {
+                self.expression();
+                if element_count == MAX_LITERAL_PARTS {
+                    self.error("Can't have more than 65535 elements in a list literal.");
+                }
+                element_count += 1;
+                if !self.do_match(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightBracket, "Expect ']' after list elements.");
+        self.emit_op(OpCode::BuildList);
+        self.emit_short(element_count.min(MAX_LITERAL_PARTS) as u16);
+    }
+
+    /// A brace in expression position starts a map literal; statements starting with one are
+    /// blocks and never reach here. Entries are inserted one at a time, so an invalid key is
+    /// reported before later entries are evaluated.
+    fn map(&mut self, _can_assign: bool) {
+        let brace_line = self.previous().line;
+        self.emit_op(OpCode::BuildMap);
+        if !self.check(TokenType::RightBrace) {
+            loop {
+                let key_start = self.peek().span.start;
+                self.expression();
+                let key_span = key_start..self.previous().span.end;
+                self.consume(TokenType::Colon, "Expect ':' after map key.");
+                self.expression();
+                self.current_chunk()
+                    .write(OpCode::MapEntry as u8, brace_line, key_span);
+                if !self.do_match(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after map entries.");
+    }
+
+    fn index(&mut self, can_assign: bool) {
+        let start = self.expression_start;
+        self.expression();
+        self.consume(TokenType::RightBracket, "Expect ']' after index.");
+        let bracket = self.previous().clone();
+
+        if can_assign && self.do_match(TokenType::Equal) {
+            self.expression();
+            self.emit_op_spanning(OpCode::SetIndex, &bracket, start);
+        } else {
+            self.emit_op_spanning(OpCode::GetIndex, &bracket, start);
+        }
+    }
+
+    fn argument_list(&mut self) -> u8 {
+        let mut argument_count: usize = 0;
+        if !self.check(TokenType::RightParen) {
+            loop {
+                self.expression();
+                if argument_count == 255 {
+                    self.error("Can't have more than 255 arguments.");
+                }
+                argument_count += 1;
+                if !self.do_match(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after arguments.");
+        argument_count.min(255) as u8
+    }
+
+    fn variable(&mut self, can_assign: bool) {
+        let name = self.previous().lexeme.clone();
+        self.named_variable(&name, can_assign);
+    }
+
+    fn this(&mut self, _can_assign: bool) {
+        if self.classes.is_empty() {
+            self.error("Can't use 'this' outside of a class.");
+            return;
+        }
+        self.variable(false);
+    }
+
+    fn super_(&mut self, _can_assign: bool) {
+        match self.classes.last() {
+            None => self.error("Can't use 'super' outside of a class."),
+            Some(class) if !class.has_superclass => {
+                self.error("Can't use 'super' in a class with no superclass.")
+            }
+            Some(_) => (),
+        }
+
+        let start = self.expression_start;
+        self.consume(TokenType::Dot, "Expect '.' after 'super'.");
+        self.consume(TokenType::Identifier, "Expect superclass method name.");
+        let name = self.previous().clone();
+        let constant = self.identifier_constant(&name.lexeme);
+
+        self.named_variable("this", false);
+        if self.do_match(TokenType::LeftParen) {
+            let argument_count = self.argument_list();
+            self.named_variable("super", false);
+            self.emit_constant_op_at(OpCode::SuperInvoke, constant, name.line, name.span);
+            self.emit_argument_count(argument_count, start);
+        } else {
+            self.named_variable("super", false);
+            self.emit_constant_op_at(OpCode::GetSuper, constant, name.line, name.span);
+        }
+    }
+
+    fn named_variable(&mut self, name: &str, can_assign: bool) {
+        // Undefined globals are reported against the name, even when assigning to one.
+        let token = self.previous();
+        let (line, span) = (token.line, token.span.clone());
+        let level = self.functions.len() - 1;
+        // Locals and upvalues take a one-byte slot, globals a two-byte constant index.
+        let (get_op, set_op, slot) = if let Some(slot) = self.resolve_local(level, name) {
+            (OpCode::GetLocal, OpCode::SetLocal, Some(slot))
+        } else if let Some(index) = self.resolve_upvalue(level, name) {
+            (OpCode::GetUpvalue, OpCode::SetUpvalue, Some(index))
+        } else {
+            (OpCode::GetGlobal, OpCode::SetGlobal, None)
+        };
+
+        let op = if can_assign && self.do_match(TokenType::Equal) {
+            self.expression();
+            set_op
+        } else {
+            get_op
+        };
+        match slot {
+            Some(slot) => self.emit_bytes(op as u8, slot),
+            None => {
+                let constant = self.identifier_constant(name);
+                self.emit_constant_op_at(op, constant, line, span);
+            }
+        }
+    }
+
+    fn resolve_local(&mut self, level: usize, name: &str) -> Option<u8> {
+        let (slot, initialized) = self.functions[level]
+            .locals
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, local)| local.name == name)
+            .map(|(slot, local)| (slot, local.depth.is_some()))?;
+
+        if !initialized {
+            self.error("Can't read local variable in its own initializer.");
+        }
+        Some(slot as u8)
+    }
+
+    fn resolve_upvalue(&mut self, level: usize, name: &str) -> Option<u8> {
+        if level == 0 {
+            return None;
+        }
+
+        if let Some(local) = self.resolve_local(level - 1, name) {
+            self.functions[level - 1].locals[local as usize].is_captured = true;
+            return Some(self.add_upvalue(level, local, true));
+        }
+
+        let upvalue = self.resolve_upvalue(level - 1, name)?;
+        Some(self.add_upvalue(level, upvalue, false))
+    }
+
+    fn add_upvalue(&mut self, level: usize, index: u8, is_local: bool) -> u8 {
+        let upvalues = &self.functions[level].upvalues;
+        if let Some(existing) = upvalues
+            .iter()
+            .position(|upvalue| upvalue.index == index && upvalue.is_local == is_local)
+        {
+            return existing as u8;
+        }
+
+        if upvalues.len() == MAX_UPVALUES {
+            self.error("Too many closure variables in function.");
+            return 0;
+        }
+
+        let function = &mut self.functions[level];
+        function.upvalues.push(UpvalueRef { index, is_local });
+        function.function.upvalue_count = function.upvalues.len();
+        (function.upvalues.len() - 1) as u8
+    }
+
+    fn parse_variable(&mut self, message: &str) -> u16 {
+        self.consume(TokenType::Identifier, message);
+
+        self.declare_variable();
+        if self.current_function().scope_depth > 0 {
+            return 0;
+        }
+
+        let name = self.previous().lexeme.clone();
+        self.identifier_constant(&name)
+    }
+
+    fn declare_variable(&mut self) {
+        let scope_depth = self.current_function().scope_depth;
+        if scope_depth == 0 {
+            return;
+        }
+
+        let name = self.previous().lexeme.clone();
+        let already_declared = self
+            .current_function()
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth.is_none_or(|depth| depth >= scope_depth))
+            .any(|local| local.name == name);
+        if already_declared {
+            self.error("Already a variable with this name in this scope.");
+        }
+
+        self.add_local(&name);
+    }
+
+    fn add_local(&mut self, name: &str) {
+        if self.current_function().locals.len() == MAX_LOCALS {
+            self.error("Too many local variables in function.");
+            return;
+        }
+
+        self.current_function().locals.push(Local {
+            name: name.to_string(),
+            depth: None,
+            is_captured: false,
+        });
+    }
+
+    fn define_variable(&mut self, global: u16) {
+        if self.current_function().scope_depth > 0 {
+            self.mark_initialized();
+            return;
+        }
+
+        self.emit_constant_op(OpCode::DefineGlobal, global);
+    }
+
+    fn mark_initialized(&mut self) {
+        let function = self.current_function();
+        if function.scope_depth == 0 {
+            return;
+        }
+        let depth = function.scope_depth;
+        if let Some(local) = function.locals.last_mut() {
+            local.depth = Some(depth);
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.current_function().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.current_function().scope_depth -= 1;
+
+        loop {
+            let function = self.current_function();
+            let depth = function.scope_depth;
+            let captured = match function.locals.last() {
+                Some(local) if local.depth.is_none_or(|local_depth| local_depth > depth) => {
+                    local.is_captured
+                }
+                _ => break,
+            };
+            function.locals.pop();
+
+            if captured {
+                self.emit_op(OpCode::CloseUpvalue);
+            } else {
+                self.emit_op(OpCode::Pop);
+            }
+        }
+    }
+
+    fn end_function(&mut self) -> (Function, Vec<UpvalueRef>) {
+        self.emit_return();
+        let state = self
+            .functions
+            .pop()
+            .expect("The script function is never popped before compiling ends.");
+        (state.function, state.upvalues)
+    }
+
+    fn current_function(&mut self) -> &mut FunctionState {
+        self.functions
+            .last_mut()
+            .expect("There is always a function being compiled.")
+    }
+
+    fn current_try(&mut self) -> &mut Try {
+        self.current_function()
+            .tries
+            .last_mut()
+            .expect("A try statement is being compiled.")
+    }
+
+    fn current_chunk(&mut self) -> &mut Chunk {
+        &mut self.current_function().function.chunk
+    }
+
+    fn identifier_constant(&mut self, name: &str) -> u16 {
+        self.make_constant(Value::String(Rc::from(name)))
+    }
+
+    /// Adds `value` to the current chunk's constants, reusing the slot of an equal number or
+    /// string already there.
+    fn make_constant(&mut self, value: Value) -> u16 {
+        let key = match &value {
+            Value::Number(number) => Some(ConstantKey::Number(number.to_bits())),
+            Value::String(string) => Some(ConstantKey::String(string.clone())),
+            _ => None,
+        };
+        if let Some(constant) = key
+            .as_ref()
+            .and_then(|key| self.current_function().constants.get(key))
+        {
+            return *constant;
+        }
+
+        let constant = self.current_chunk().add_constant(value);
+        if constant > u16::MAX as usize {
+            self.error("Too many constants in one chunk.");
+            return 0;
+        }
+        if let Some(key) = key {
+            self.current_function()
+                .constants
+                .insert(key, constant as u16);
+        }
+        constant as u16
+    }
+
+    fn emit_constant(&mut self, value: Value) {
+        let constant = self.make_constant(value);
+        self.emit_constant_op(OpCode::Constant, constant);
+    }
+
+    /// Emits an instruction whose operand is the index of one of the chunk's constants, behind
+    /// a `Wide` prefix if the index needs two bytes.
+    fn emit_constant_op(&mut self, op: OpCode, constant: u16) {
+        let token = self.previous();
+        let (line, span) = (token.line, token.span.clone());
+        self.emit_constant_op_at(op, constant, line, span);
+    }
+
+    /// Like `emit_constant_op`, but attributes every byte of the instruction to `line` and
+    /// `span` rather than the previous token.
+    fn emit_constant_op_at(&mut self, op: OpCode, constant: u16, line: usize, span: Range<usize>) {
+        let chunk = self.current_chunk();
+        if constant <= u8::MAX as u16 {
+            chunk.write(op as u8, line, span.clone());
+            chunk.write(constant as u8, line, span);
+        } else {
+            let [high, low] = constant.to_be_bytes();
+            chunk.write(OpCode::Wide as u8, line, span.clone());
+            chunk.write(op as u8, line, span.clone());
+            chunk.write(high, line, span.clone());
+            chunk.write(low, line, span);
+        }
+    }
+
+    /// Emits the argument count that ends a call instruction. It carries the closing paren's
+    /// line and the span of the whole call from `start`, which failed calls are reported
+    /// against.
+    fn emit_argument_count(&mut self, argument_count: u8, start: usize) {
+        let paren = self.previous();
+        let (line, span) = (paren.line, start..paren.span.end);
+        self.current_chunk().write(argument_count, line, span);
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let token = self.previous();
+        let (line, span) = (token.line, token.span.clone());
+        self.current_chunk().write(byte, line, span);
+    }
+
+    fn emit_bytes(&mut self, first: u8, second: u8) {
+        self.emit_byte(first);
+        self.emit_byte(second);
+    }
+
+    fn emit_op(&mut self, op: OpCode) {
+        self.emit_byte(op as u8);
+    }
+
+    fn emit_short(&mut self, short: u16) {
+        let [high, low] = short.to_be_bytes();
+        self.emit_bytes(high, low);
+    }
+
+    /// Emits an instruction on `token`'s line whose span runs from `start` through the previous
+    /// token, so runtime errors underline the whole expression like the tree-walker's do.
+    fn emit_op_spanning(&mut self, op: OpCode, token: &Token, start: usize) {
+        let span = start..self.previous().span.end;
+        self.current_chunk().write(op as u8, token.line, span);
+    }
+
+    fn emit_return(&mut self) {
+        self.emit_return_value();
+        self.emit_op(OpCode::Return);
+    }
+
+    /// Pushes what a function returns without a value: `this` in an initializer, else `nil`.
+    fn emit_return_value(&mut self) {
+        if self.current_function().kind == FunctionKind::Initializer {
+            self.emit_bytes(OpCode::GetLocal as u8, 0);
+        } else {
+            self.emit_op(OpCode::Nil);
+        }
+    }
+
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_op(op);
+        self.emit_byte(0xff);
+        self.emit_byte(0xff);
+        self.current_chunk().code.len() - 2
+    }
+
+    fn patch_jump(&mut self, offset: usize) {
+        let jump = self.current_chunk().code.len() - offset - 2;
+        if jump > u16::MAX as usize {
+            self.error("Too much code to jump over.");
+        }
+
+        let [high, low] = (jump as u16).to_be_bytes();
+        let code = &mut self.current_chunk().code;
+        code[offset] = high;
+        code[offset + 1] = low;
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.emit_op(OpCode::Loop);
+
+        let offset = self.current_chunk().code.len() - loop_start + 2;
+        if offset > u16::MAX as usize {
+            self.error("Loop body too large.");
+        }
+
+        let [high, low] = (offset as u16).to_be_bytes();
+        self.emit_byte(high);
+        self.emit_byte(low);
+    }
+
+    fn synchronize(&mut self) {
+        self.panic_mode = false;
+
+        while self.peek().token_type != TokenType::Eof {
+            if self.previous().token_type == TokenType::Semicolon {
+                return;
+            }
+
+            match self.peek().token_type {
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::Throw
+                | TokenType::Try => return,
+                _ => self.advance(),
+            }
+        }
+    }
+
+    /// Compiles with `compile` one level of nesting deeper, failing once that passes
+    /// `MAX_NESTING` or would leave too little native stack. The token that would have nested too deeply is skipped, so that recovery
+    /// can't stop in front of it again.
+    fn nested(&mut self, compile: impl FnOnce(&mut Self)) {
+        if self.nesting == MAX_NESTING || self.stack_limit.exceeded() {
+            self.error_at_current("Too much nesting.");
+            self.advance();
+            return;
+        }
+
+        self.nesting += 1;
+        compile(self);
+        self.nesting -= 1;
+    }
+
+    fn advance(&mut self) {
+        self.previous = self.current;
+        if self.peek().token_type != TokenType::Eof {
+            self.current += 1;
+        }
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
+    }
+
+    fn previous(&self) -> &Token {
+        &self.tokens[self.previous]
+    }
+
+    fn check(&self, token_type: TokenType) -> bool {
+        self.peek().token_type == token_type
+    }
+
+    fn check_next(&self, token_type: TokenType) -> bool {
+        self.tokens
+            .get(self.current + 1)
+            .is_some_and(|token| token.token_type == token_type)
+    }
+
+    fn do_match(&mut self, token_type: TokenType) -> bool {
+        if !self.check(token_type) {
+            return false;
+        }
+        self.advance();
+        true
+    }
+
+    fn consume(&mut self, token_type: TokenType, message: &str) {
+        if self.check(token_type) {
+            self.advance();
+        } else {
+            self.error_at_current(message);
+        }
+    }
+
+    fn error(&mut self, message: &str) {
+        let token = self.previous().clone();
+        self.error_at(token, message);
+    }
+
+    fn error_at_current(&mut self, message: &str) {
+        let token = self.peek().clone();
+        self.error_at(token, message);
+    }
+
+    fn error_at(&mut self, token: Token, message: &str) {
+        if self.panic_mode {
+            return;
+        }
+        self.panic_mode = true;
+        self.errors.push(ParseError {
+            token: Box::new(token),
+            message: message.to_string(),
+        });
+    }
+}
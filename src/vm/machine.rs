@@ -0,0 +1,950 @@
+use std::{cell::RefCell, collections::HashMap, ops::Range, rc::Rc};
+
+use crate::{
+    interpreter::{Interpreter, RuntimeError, TraceFrame},
+    scanner::Scanner,
+    LoxError,
+};
+
+use super::{
+    chunk::OpCode,
+    compiler::Compiler,
+    natives::{self, list_position},
+    value::{
+        BoundMethod, BoundNative, Class, Closure, Instance, Map, Native, NativeFn, Upvalue, Value,
+    },
+};
+
+struct CallFrame {
+    closure: Rc<Closure>,
+    ip: usize,
+    /// Index of the stack slot holding the callee; the frame's locals start here.
+    slots: usize,
+    /// Set by a `Wide` prefix: the next constant operand is two bytes rather than one.
+    wide: bool,
+}
+
+impl CallFrame {
+    fn read_byte(&mut self) -> u8 {
+        let byte = self.closure.function.chunk.code[self.ip];
+        self.ip += 1;
+        byte
+    }
+
+    fn read_short(&mut self) -> u16 {
+        let high = self.read_byte();
+        let low = self.read_byte();
+        u16::from_be_bytes([high, low])
+    }
+
+    fn read_constant(&mut self) -> Value {
+        let index = if self.wide {
+            self.wide = false;
+            self.read_short() as usize
+        } else {
+            self.read_byte() as usize
+        };
+        self.closure.function.chunk.constants[index].clone()
+    }
+
+    /// The source line of the instruction being executed.
+    fn line(&self) -> usize {
+        self.closure.function.chunk.lines[self.ip - 1]
+    }
+
+    fn read_string(&mut self) -> Rc<str> {
+        match self.read_constant() {
+            Value::String(string) => string,
+            constant => panic!("Expected a string constant, found {:?}", constant),
+        }
+    }
+}
+
+/// An active `try` statement, recording where to resume if an error escapes the code it
+/// protects.
+struct Handler {
+    /// The number of frames below the one that installed the handler.
+    frame_count: usize,
+    /// The stack height to unwind to. A `finally` handler's completion slots are the two just
+    /// below it.
+    stack_len: usize,
+    /// The instruction to resume at, in the frame that installed the handler.
+    target: usize,
+    /// Whether the handler runs a `finally` block rather than a `catch` clause.
+    finally: bool,
+}
+
+/// Why a call failed. Each kind is reported against the same part of the call expression as
+/// in the tree-walker.
+enum CallError {
+    /// The callee can't be called with these arguments; reported against the whole call.
+    Call(String),
+    /// The call itself failed, by overflowing the stack or in a native; reported against the
+    /// closing paren.
+    Paren(String),
+    /// The invoked method doesn't exist; reported against its name.
+    Property(String),
+    /// The receiver of an invoked method has no properties; reported against the receiver and
+    /// the method name.
+    Receiver(String),
+}
+
+/// Stack-based virtual machine executing bytecode produced by the compiler. Globals persist
+/// across calls to [`Vm::interpret`].
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    globals: HashMap<Rc<str>, Value>,
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    max_call_depth: usize,
+    handlers: Vec<Handler>,
+    /// The value of the `throw` currently unwinding, until a handler claims it.
+    thrown: Option<Value>,
+    /// The class of the values runtime errors become when caught.
+    error_class: Rc<Class>,
+    /// The `Error` global. Calls to it are handled by the VM itself.
+    error_native: Rc<Native>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        let mut vm = Self {
+            stack: Vec::with_capacity(256),
+            frames: Vec::with_capacity(64),
+            globals: HashMap::new(),
+            open_upvalues: vec![],
+            max_call_depth: Interpreter::DEFAULT_MAX_CALL_DEPTH,
+            handlers: vec![],
+            thrown: None,
+            error_class: Rc::new(Class {
+                name: "Error".to_string(),
+                methods: RefCell::new(HashMap::new()),
+            }),
+            error_native: Rc::new(Native {
+                name: "Error".to_string(),
+                arity: 1,
+                function: natives::error,
+            }),
+        };
+        vm.define_native("clock", 0, natives::clock);
+        vm.globals
+            .insert(Rc::from("Error"), Value::Native(vm.error_native.clone()));
+        vm
+    }
+
+    pub fn define_native(&mut self, name: &str, arity: usize, function: NativeFn) {
+        let native = Native {
+            name: name.to_string(),
+            arity,
+            function,
+        };
+        self.globals
+            .insert(Rc::from(name), Value::Native(Rc::new(native)));
+    }
+
+    pub fn max_call_depth(&self) -> usize {
+        self.max_call_depth
+    }
+
+    /// Sets how many Lox calls may be active at once, as
+    /// [`Interpreter::set_max_call_depth`] does for the tree-walker. A call beyond the limit
+    /// raises a "Stack overflow." runtime error.
+    pub fn set_max_call_depth(&mut self, max_call_depth: usize) {
+        self.max_call_depth = max_call_depth;
+    }
+
+    pub fn interpret(&mut self, source: &str) -> Result<(), LoxError> {
+        let (tokens, errors) = Scanner::new(source).scan_tokens();
+        if !errors.is_empty() {
+            return Err(LoxError::Scan(errors));
+        }
+        let function = Compiler::new(tokens).compile().map_err(LoxError::Parse)?;
+
+        let closure = Rc::new(Closure {
+            function: Rc::new(function),
+            upvalues: vec![],
+        });
+        self.stack.push(Value::Closure(closure.clone()));
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            slots: 0,
+            wide: false,
+        });
+
+        self.run().map_err(|error| {
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+            self.handlers.clear();
+            self.thrown = None;
+            LoxError::Runtime(error)
+        })
+    }
+
+    fn run(&mut self) -> Result<(), RuntimeError> {
+        let mut frame = self
+            .frames
+            .pop()
+            .expect("interpret pushes the script frame before running");
+        loop {
+            match self.execute(&mut frame) {
+                Ok(()) => return Ok(()),
+                Err(error) => self.unwind(&mut frame, error)?,
+            }
+        }
+    }
+
+    /// Executes instructions until the script returns or an error is raised. `frame` is the
+    /// frame being executed, held outside `frames`.
+    fn execute(&mut self, frame: &mut CallFrame) -> Result<(), RuntimeError> {
+        macro_rules! binary_op {
+            ($variant:ident, $op:tt) => {{
+                match (self.peek(1), self.peek(0)) {
+                    (Value::Number(a), Value::Number(b)) => {
+                        let result = Value::$variant(*a $op *b);
+                        self.stack.pop();
+                        *self.peek_mut(0) = result;
+                    }
+                    _ => return Err(self.error(frame, "Operands must be numbers.")),
+                }
+            }};
+        }
+
+        loop {
+            match OpCode::from(frame.read_byte()) {
+                OpCode::Constant => {
+                    let constant = frame.read_constant();
+                    self.stack.push(constant);
+                }
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Bool(true)),
+                OpCode::False => self.stack.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.stack.pop();
+                }
+                OpCode::GetLocal => {
+                    let slot = frame.read_byte() as usize;
+                    let value = self.stack[frame.slots + slot].clone();
+                    self.stack.push(value);
+                }
+                OpCode::SetLocal => {
+                    let slot = frame.read_byte() as usize;
+                    self.stack[frame.slots + slot] = self.peek(0).clone();
+                }
+                OpCode::GetGlobal => {
+                    let name = frame.read_string();
+                    match self.globals.get(&name) {
+                        Some(value) => {
+                            let value = value.clone();
+                            self.stack.push(value);
+                        }
+                        None => {
+                            let message = format!("Undefined variable '{}'.", name);
+                            return Err(self.error(frame, &message));
+                        }
+                    }
+                }
+                OpCode::DefineGlobal => {
+                    let name = frame.read_string();
+                    let value = self.stack.pop().expect("stack underflow");
+                    self.globals.insert(name, value);
+                }
+                OpCode::SetGlobal => {
+                    let name = frame.read_string();
+                    let value = self.peek(0).clone();
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => {
+                            let message = format!("Undefined variable '{}'.", name);
+                            return Err(self.error(frame, &message));
+                        }
+                    }
+                }
+                OpCode::GetUpvalue => {
+                    let slot = frame.read_byte() as usize;
+                    let value = match &*frame.closure.upvalues[slot].borrow() {
+                        Upvalue::Open(index) => self.stack[*index].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetUpvalue => {
+                    let slot = frame.read_byte() as usize;
+                    let value = self.peek(0).clone();
+                    let mut upvalue = frame.closure.upvalues[slot].borrow_mut();
+                    match &mut *upvalue {
+                        Upvalue::Open(index) => self.stack[*index] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    }
+                }
+                OpCode::GetProperty => {
+                    let name = frame.read_string();
+                    let instance = match self.peek(0) {
+                        Value::Instance(instance) => instance.clone(),
+                        Value::List(_) | Value::Map(_) => {
+                            match natives::method(self.peek(0), &name) {
+                                Some(method) => {
+                                    let bound = BoundNative {
+                                        receiver: self.peek(0).clone(),
+                                        method: Rc::new(method),
+                                    };
+                                    *self.peek_mut(0) = Value::BoundNative(Rc::new(bound));
+                                }
+                                None => return Err(self.property_error(frame, &name)),
+                            }
+                            continue;
+                        }
+                        _ => return Err(self.error(frame, "Only instances have properties.")),
+                    };
+
+                    let field = instance.fields.borrow().get(&name).cloned();
+                    match field {
+                        Some(value) => *self.peek_mut(0) = value,
+                        None => {
+                            if !self.bind_method(&instance.class, &name) {
+                                return Err(self.property_error(frame, &name));
+                            }
+                        }
+                    }
+                }
+                OpCode::SetProperty => {
+                    let name = frame.read_string();
+                    let instance = match self.peek(1) {
+                        Value::Instance(instance) => instance.clone(),
+                        _ => return Err(self.error(frame, "Only instances have fields.")),
+                    };
+
+                    let value = self.stack.pop().expect("stack underflow");
+                    instance.fields.borrow_mut().insert(name, value.clone());
+                    *self.peek_mut(0) = value;
+                }
+                OpCode::GetSuper => {
+                    let name = frame.read_string();
+                    let superclass = self.pop_class();
+                    if !self.bind_method(&superclass, &name) {
+                        let message = format!("Undefined property '{}'.", name);
+                        return Err(self.error(frame, &message));
+                    }
+                }
+                OpCode::Equal => {
+                    let b = self.stack.pop().expect("stack underflow");
+                    let a = self.peek_mut(0);
+                    *a = Value::Bool(*a == b);
+                }
+                OpCode::Greater => binary_op!(Bool, >),
+                OpCode::Less => binary_op!(Bool, <),
+                OpCode::Add => match (self.peek(1), self.peek(0)) {
+                    (Value::Number(a), Value::Number(b)) => {
+                        let result = Value::Number(a + b);
+                        self.stack.pop();
+                        *self.peek_mut(0) = result;
+                    }
+                    (Value::String(a), Value::String(b)) => {
+                        let result = Value::String(Rc::from(format!("{}{}", a, b)));
+                        self.stack.pop();
+                        *self.peek_mut(0) = result;
+                    }
+                    _ => {
+                        return Err(
+                            self.error(frame, "Operands must be two numbers or two strings.")
+                        )
+                    }
+                },
+                OpCode::Subtract => binary_op!(Number, -),
+                OpCode::Multiply => binary_op!(Number, *),
+                OpCode::Divide => binary_op!(Number, /),
+                OpCode::Not => {
+                    let value = self.peek_mut(0);
+                    *value = Value::Bool(value.is_falsey());
+                }
+                OpCode::Negate => match self.peek_mut(0) {
+                    Value::Number(number) => *number = -*number,
+                    _ => return Err(self.error(frame, "Operand must be a number.")),
+                },
+                OpCode::Print => {
+                    println!("{}", self.stack.pop().expect("stack underflow"));
+                }
+                OpCode::Jump => {
+                    let offset = frame.read_short() as usize;
+                    frame.ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = frame.read_short() as usize;
+                    if self.peek(0).is_falsey() {
+                        frame.ip += offset;
+                    }
+                }
+                OpCode::Loop => {
+                    let offset = frame.read_short() as usize;
+                    frame.ip -= offset;
+                }
+                OpCode::Call => {
+                    let argument_count = frame.read_byte() as usize;
+                    let callee = self.peek(argument_count).clone();
+                    match self.call_value(callee, argument_count, frame.line()) {
+                        Ok(Some(new_frame)) => {
+                            self.frames.push(std::mem::replace(frame, new_frame))
+                        }
+                        Ok(None) => (),
+                        Err(error) => return Err(self.call_error(frame, error)),
+                    }
+                }
+                OpCode::Invoke => {
+                    let name = frame.read_string();
+                    let argument_count = frame.read_byte() as usize;
+                    match self.invoke(&name, argument_count, frame.line()) {
+                        Ok(Some(new_frame)) => {
+                            self.frames.push(std::mem::replace(frame, new_frame))
+                        }
+                        Ok(None) => (),
+                        Err(error) => return Err(self.call_error(frame, error)),
+                    }
+                }
+                OpCode::SuperInvoke => {
+                    let name = frame.read_string();
+                    let argument_count = frame.read_byte() as usize;
+                    let superclass = self.pop_class();
+                    match self.invoke_from_class(&superclass, &name, argument_count) {
+                        Ok(new_frame) => self.frames.push(std::mem::replace(frame, new_frame)),
+                        Err(error) => return Err(self.call_error(frame, error)),
+                    }
+                }
+                OpCode::Closure => {
+                    let function = match frame.read_constant() {
+                        Value::Function(function) => function,
+                        constant => panic!("Expected a function constant, found {:?}", constant),
+                    };
+
+                    let mut upvalues = Vec::with_capacity(function.upvalue_count);
+                    for _ in 0..function.upvalue_count {
+                        let is_local = frame.read_byte() == 1;
+                        let index = frame.read_byte() as usize;
+                        if is_local {
+                            upvalues.push(self.capture_upvalue(frame.slots + index));
+                        } else {
+                            upvalues.push(frame.closure.upvalues[index].clone());
+                        }
+                    }
+
+                    let closure = Closure { function, upvalues };
+                    self.stack.push(Value::Closure(Rc::new(closure)));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.stack.pop();
+                }
+                OpCode::Return => {
+                    let result = self.stack.pop().expect("stack underflow");
+                    self.close_upvalues(frame.slots);
+                    self.stack.truncate(frame.slots);
+
+                    match self.frames.pop() {
+                        Some(caller) => {
+                            self.stack.push(result);
+                            *frame = caller;
+                        }
+                        None => return Ok(()),
+                    }
+                }
+                OpCode::Class => {
+                    let name = frame.read_string();
+                    let class = Class {
+                        name: name.to_string(),
+                        methods: RefCell::new(HashMap::new()),
+                    };
+                    self.stack.push(Value::Class(Rc::new(class)));
+                }
+                OpCode::Inherit => {
+                    let superclass = match self.peek(1) {
+                        Value::Class(superclass) => superclass.clone(),
+                        _ => return Err(self.error(frame, "Superclass must be a class.")),
+                    };
+                    if let Value::Class(subclass) = self.peek(0) {
+                        let methods = superclass.methods.borrow();
+                        subclass.methods.borrow_mut().extend(
+                            methods
+                                .iter()
+                                .map(|(name, method)| (name.clone(), method.clone())),
+                        );
+                    }
+                    self.stack.pop();
+                }
+                OpCode::BuildList => {
+                    let element_count = frame.read_short() as usize;
+                    let elements = self.stack.split_off(self.stack.len() - element_count);
+                    self.stack
+                        .push(Value::List(Rc::new(RefCell::new(elements))));
+                }
+                OpCode::BuildMap => {
+                    let map = Map::default();
+                    self.stack.push(Value::Map(Rc::new(RefCell::new(map))));
+                }
+                OpCode::MapEntry => {
+                    let value = self.stack.pop().expect("stack underflow");
+                    let key = self.stack.pop().expect("stack underflow");
+                    let result = match self.peek(0) {
+                        Value::Map(map) => map.borrow_mut().set(key, value),
+                        map => panic!("Expected a map literal on the stack, found {:?}", map),
+                    };
+                    if let Err(message) = result {
+                        return Err(self.error(frame, &message));
+                    }
+                }
+                OpCode::BuildString => {
+                    let part_count = frame.read_short() as usize;
+                    let parts = self.stack.split_off(self.stack.len() - part_count);
+                    let string: String = parts.iter().map(|part| part.to_string()).collect();
+                    self.stack.push(Value::String(Rc::from(string)));
+                }
+                OpCode::GetIndex => {
+                    let index = self.stack.pop().expect("stack underflow");
+                    let element = match self.peek(0) {
+                        Value::List(list) => {
+                            let list = list.borrow();
+                            list_position(list.len(), &index, false)
+                                .map(|position| list[position].clone())
+                        }
+                        Value::Map(map) => map.borrow().get(&index),
+                        _ => Err("Only lists and maps can be indexed.".to_string()),
+                    };
+                    match element {
+                        Ok(element) => *self.peek_mut(0) = element,
+                        Err(message) => return Err(self.error(frame, &message)),
+                    }
+                }
+                OpCode::SetIndex => {
+                    let value = self.stack.pop().expect("stack underflow");
+                    let index = self.stack.pop().expect("stack underflow");
+                    let result = match self.peek(0) {
+                        Value::List(list) => {
+                            let mut list = list.borrow_mut();
+                            list_position(list.len(), &index, false)
+                                .map(|position| list[position] = value.clone())
+                        }
+                        Value::Map(map) => map.borrow_mut().set(index, value.clone()),
+                        _ => Err("Only lists and maps can be indexed.".to_string()),
+                    };
+                    match result {
+                        Ok(()) => *self.peek_mut(0) = value,
+                        Err(message) => return Err(self.error(frame, &message)),
+                    }
+                }
+                OpCode::Throw => {
+                    let value = self.stack.pop().expect("stack underflow");
+                    return Err(self.throw(frame, value));
+                }
+                OpCode::PushCatch => {
+                    let offset = frame.read_short() as usize;
+                    self.handlers.push(Handler {
+                        frame_count: self.frames.len(),
+                        stack_len: self.stack.len(),
+                        target: frame.ip + offset,
+                        finally: false,
+                    });
+                }
+                OpCode::PushFinally => {
+                    let offset = frame.read_short() as usize;
+                    let target = frame.ip + offset;
+                    let completion = frame.read_byte() as usize;
+                    self.handlers.push(Handler {
+                        frame_count: self.frames.len(),
+                        stack_len: frame.slots + completion + 2,
+                        target,
+                        finally: true,
+                    });
+                }
+                OpCode::PopHandler => {
+                    self.handlers.pop();
+                }
+                OpCode::EndFinally => {
+                    let kind = self.stack.pop().expect("stack underflow");
+                    let value = self.stack.pop().expect("stack underflow");
+                    if let Value::Unwinding(error) = kind {
+                        if let RuntimeError::Thrown { .. } = *error {
+                            self.thrown = Some(value);
+                        }
+                        return Err(Rc::try_unwrap(error).unwrap_or_else(|error| (*error).clone()));
+                    }
+                }
+                OpCode::Wide => frame.wide = true,
+                OpCode::Method => {
+                    let name = frame.read_string();
+                    if let (Value::Closure(method), Value::Class(class)) =
+                        (self.peek(0), self.peek(1))
+                    {
+                        class.methods.borrow_mut().insert(name, method.clone());
+                    }
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+
+    /// Resumes execution at the innermost handler for `error`, or hands the error back if no
+    /// `try` statement is active.
+    fn unwind(&mut self, frame: &mut CallFrame, error: RuntimeError) -> Result<(), RuntimeError> {
+        let handler = match self.handlers.pop() {
+            Some(handler) => handler,
+            None => return Err(error),
+        };
+        let value = match &error {
+            RuntimeError::Error { line, message, .. } => {
+                self.error_value(Value::String(Rc::from(message.as_str())), *line)
+            }
+            RuntimeError::Thrown { .. } => self
+                .thrown
+                .take()
+                .expect("Thrown errors leave their value with the VM."),
+        };
+
+        while self.frames.len() > handler.frame_count {
+            *frame = self.frames.pop().expect("stack underflow");
+        }
+        self.close_upvalues(handler.stack_len);
+        self.stack.truncate(handler.stack_len);
+        if handler.finally {
+            let completion = self.stack.len() - 2;
+            self.stack[completion] = value;
+            self.stack[completion + 1] = Value::Unwinding(Rc::new(error));
+        } else {
+            self.stack.push(value);
+        }
+        frame.ip = handler.target;
+        Ok(())
+    }
+
+    /// Creates the value a `catch` clause receives for a runtime error: an `Error` instance with
+    /// `message` and `line` fields.
+    fn error_value(&self, message: Value, line: usize) -> Value {
+        let fields = HashMap::from([
+            (Rc::from("message"), message),
+            (Rc::from("line"), Value::Number(line as f64)),
+        ]);
+        Value::Instance(Rc::new(Instance {
+            class: self.error_class.clone(),
+            fields: RefCell::new(fields),
+        }))
+    }
+
+    /// Describes a thrown value for when no `catch` claims it.
+    fn describe_thrown(&self, value: &Value) -> String {
+        if let Value::Instance(instance) = value {
+            if Rc::ptr_eq(&instance.class, &self.error_class) {
+                if let Some(message) = instance.fields.borrow().get("message") {
+                    return message.to_string();
+                }
+            }
+        }
+        value.to_string()
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn peek_mut(&mut self, distance: usize) -> &mut Value {
+        let index = self.stack.len() - 1 - distance;
+        &mut self.stack[index]
+    }
+
+    fn pop_class(&mut self) -> Rc<Class> {
+        match self.stack.pop() {
+            Some(Value::Class(class)) => class,
+            value => panic!("Expected a class on the stack, found {:?}", value),
+        }
+    }
+
+    /// Calls `callee` with the `argument_count` values on top of the stack. Returns the frame to
+    /// switch to for closures, or `None` when the call already completed.
+    fn call_value(
+        &mut self,
+        callee: Value,
+        argument_count: usize,
+        line: usize,
+    ) -> Result<Option<CallFrame>, CallError> {
+        match callee {
+            // `Error` needs the VM's error class and the line of the call, which natives can't
+            // see.
+            Value::Native(native) if Rc::ptr_eq(&native, &self.error_native) => {
+                if argument_count != 1 {
+                    let message = format!("Expected 1 arguments but got {}.", argument_count);
+                    return Err(CallError::Call(message));
+                }
+                let message = self.stack.pop().expect("stack underflow");
+                *self.peek_mut(0) = self.error_value(message, line);
+                Ok(None)
+            }
+            Value::BoundMethod(bound) => {
+                *self.peek_mut(argument_count) = bound.receiver.clone();
+                self.call(bound.method.clone(), argument_count).map(Some)
+            }
+            Value::Class(class) => {
+                let instance = Instance {
+                    class: class.clone(),
+                    fields: RefCell::new(HashMap::new()),
+                };
+                *self.peek_mut(argument_count) = Value::Instance(Rc::new(instance));
+
+                let initializer = class.methods.borrow().get("init").cloned();
+                match initializer {
+                    Some(initializer) => self.call(initializer, argument_count).map(Some),
+                    None if argument_count != 0 => Err(CallError::Call(format!(
+                        "Expected 0 arguments but got {}.",
+                        argument_count
+                    ))),
+                    None => Ok(None),
+                }
+            }
+            Value::Closure(closure) => self.call(closure, argument_count).map(Some),
+            Value::Native(native) => self.call_native(&native, argument_count, false),
+            Value::BoundNative(bound) => {
+                *self.peek_mut(argument_count) = bound.receiver.clone();
+                self.call_native(&bound.method, argument_count, true)
+            }
+            _ => Err(CallError::Call(
+                "Can only call functions and classes.".to_string(),
+            )),
+        }
+    }
+
+    /// Runs a native function to completion. With `with_receiver` the value in the callee's slot
+    /// is passed to it as an extra first argument.
+    fn call_native(
+        &mut self,
+        native: &Native,
+        argument_count: usize,
+        with_receiver: bool,
+    ) -> Result<Option<CallFrame>, CallError> {
+        if argument_count != native.arity {
+            return Err(CallError::Call(format!(
+                "Expected {} arguments but got {}.",
+                native.arity, argument_count
+            )));
+        }
+
+        let callee_slot = self.stack.len() - argument_count - 1;
+        let arguments_start = if with_receiver {
+            callee_slot
+        } else {
+            callee_slot + 1
+        };
+        let result = (native.function)(&self.stack[arguments_start..]).map_err(CallError::Paren)?;
+        self.stack.truncate(callee_slot);
+        self.stack.push(result);
+        Ok(None)
+    }
+
+    fn call(
+        &mut self,
+        closure: Rc<Closure>,
+        argument_count: usize,
+    ) -> Result<CallFrame, CallError> {
+        if argument_count != closure.function.arity {
+            return Err(CallError::Call(format!(
+                "Expected {} arguments but got {}.",
+                closure.function.arity, argument_count
+            )));
+        }
+
+        // The frame being called from is held outside `frames` while it runs, so `frames` holds
+        // the script and every active call but the innermost.
+        if self.frames.len() >= self.max_call_depth {
+            return Err(CallError::Paren("Stack overflow.".to_string()));
+        }
+
+        Ok(CallFrame {
+            closure,
+            ip: 0,
+            slots: self.stack.len() - argument_count - 1,
+            wide: false,
+        })
+    }
+
+    fn invoke(
+        &mut self,
+        name: &str,
+        argument_count: usize,
+        line: usize,
+    ) -> Result<Option<CallFrame>, CallError> {
+        let instance = match self.peek(argument_count) {
+            Value::Instance(instance) => instance.clone(),
+            receiver @ (Value::List(_) | Value::Map(_)) => {
+                return match natives::method(receiver, name) {
+                    Some(method) => self.call_native(&method, argument_count, true),
+                    None => Err(CallError::Property(format!(
+                        "Undefined property '{}'.",
+                        name
+                    ))),
+                }
+            }
+            _ => {
+                let message = "Only instances have properties.".to_string();
+                return Err(CallError::Receiver(message));
+            }
+        };
+
+        let field = instance.fields.borrow().get(name).cloned();
+        if let Some(value) = field {
+            *self.peek_mut(argument_count) = value.clone();
+            return self.call_value(value, argument_count, line);
+        }
+
+        self.invoke_from_class(&instance.class, name, argument_count)
+            .map(Some)
+    }
+
+    fn invoke_from_class(
+        &mut self,
+        class: &Rc<Class>,
+        name: &str,
+        argument_count: usize,
+    ) -> Result<CallFrame, CallError> {
+        let method = class.methods.borrow().get(name).cloned();
+        match method {
+            Some(method) => self.call(method, argument_count),
+            None => Err(CallError::Property(format!(
+                "Undefined property '{}'.",
+                name
+            ))),
+        }
+    }
+
+    /// Replaces the receiver on top of the stack with `name` bound to it, if `class` has such a
+    /// method.
+    fn bind_method(&mut self, class: &Rc<Class>, name: &str) -> bool {
+        let method = match class.methods.borrow().get(name) {
+            Some(method) => method.clone(),
+            None => return false,
+        };
+
+        let bound = BoundMethod {
+            receiver: self.peek(0).clone(),
+            method,
+        };
+        *self.peek_mut(0) = Value::BoundMethod(Rc::new(bound));
+        true
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(index) if index == slot));
+        if let Some(upvalue) = existing {
+            return upvalue.clone();
+        }
+
+        let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(upvalue.clone());
+        upvalue
+    }
+
+    /// Moves every captured variable at or above stack slot `last` off the stack and into its
+    /// upvalue.
+    fn close_upvalues(&mut self, last: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let slot = match *upvalue.borrow() {
+                Upvalue::Open(slot) => slot,
+                Upvalue::Closed(_) => return false,
+            };
+            if slot >= last {
+                *upvalue.borrow_mut() = Upvalue::Closed(stack[slot].clone());
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    fn error(&self, frame: &CallFrame, message: &str) -> RuntimeError {
+        let span = frame.closure.function.chunk.spans[frame.ip - 1].clone();
+        self.error_at(frame, frame.line(), span, message.to_string())
+    }
+
+    fn error_at(
+        &self,
+        frame: &CallFrame,
+        line: usize,
+        span: Range<usize>,
+        message: String,
+    ) -> RuntimeError {
+        RuntimeError::Error {
+            line,
+            span,
+            message,
+            trace: self.trace(frame, line),
+        }
+    }
+
+    /// Reports an undefined property of a `GetProperty` instruction against its name, which
+    /// ends the instruction's `object.name` span.
+    fn property_error(&self, frame: &CallFrame, name: &str) -> RuntimeError {
+        let span = &frame.closure.function.chunk.spans[frame.ip - 1];
+        let name_span = span.end - name.len()..span.end;
+        let message = format!("Undefined property '{}'.", name);
+        self.error_at(frame, frame.line(), name_span, message)
+    }
+
+    /// Reports a failed call instruction. Its last byte, the argument count, spans the whole
+    /// call, which ends with the closing paren; an invoke's method name comes just before it.
+    fn call_error(&self, frame: &CallFrame, error: CallError) -> RuntimeError {
+        let chunk = &frame.closure.function.chunk;
+        let call = chunk.spans[frame.ip - 1].clone();
+        let (name_line, name) = (chunk.lines[frame.ip - 2], &chunk.spans[frame.ip - 2]);
+        match error {
+            CallError::Call(message) => self.error_at(frame, frame.line(), call, message),
+            CallError::Paren(message) => {
+                self.error_at(frame, frame.line(), call.end - 1..call.end, message)
+            }
+            CallError::Property(message) => self.error_at(frame, name_line, name.clone(), message),
+            CallError::Receiver(message) => {
+                self.error_at(frame, name_line, call.start..name.end, message)
+            }
+        }
+    }
+
+    /// Raises `value` with `throw`, keeping the value until a handler claims it.
+    fn throw(&mut self, frame: &CallFrame, value: Value) -> RuntimeError {
+        let message = self.describe_thrown(&value);
+        self.thrown = Some(value);
+        RuntimeError::Thrown {
+            line: frame.line(),
+            span: frame.closure.function.chunk.spans[frame.ip - 1].clone(),
+            message,
+            trace: self.trace(frame, frame.line()),
+        }
+    }
+
+    /// Lists the active calls from `frame` outwards, with `frame` at `line` and ending with
+    /// `<script>` like the tree-walker's traces.
+    fn trace(&self, frame: &CallFrame, line: usize) -> Vec<TraceFrame> {
+        let callers = self
+            .frames
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(depth, frame)| (depth, frame, frame.line()));
+        std::iter::once((self.frames.len(), frame, line))
+            .chain(callers)
+            .map(|(depth, frame, line)| {
+                let function = &frame.closure.function;
+                TraceFrame {
+                    function: match &function.name {
+                        _ if depth == 0 => "<script>".to_string(),
+                        Some(name) => name.clone(),
+                        None => "<anonymous>".to_string(),
+                    },
+                    line,
+                }
+            })
+            .collect()
+    }
+}
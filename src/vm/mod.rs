@@ -0,0 +1,12 @@
+//! Bytecode backend: a single-pass compiler from scanner tokens to a [`Chunk`] and a stack-based
+//! virtual machine that executes it.
+
+pub mod chunk;
+pub mod compiler;
+pub mod machine;
+pub mod natives;
+pub mod value;
+
+pub use chunk::{Chunk, OpCode};
+pub use compiler::Compiler;
+pub use machine::Vm;
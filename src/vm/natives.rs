@@ -0,0 +1,143 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::value::{Map, Native, NativeFn, Value};
+
+pub fn clock(_: &[Value]) -> Result<Value, String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|error| error.to_string())?;
+    Ok(Value::Number(now.as_secs() as f64))
+}
+
+/// Stands behind the `Error` global. The VM handles calls to it itself, as creating an error
+/// needs the VM's `Error` class and the line of the call.
+pub fn error(_: &[Value]) -> Result<Value, String> {
+    unreachable!("Calls to Error are handled by the VM.")
+}
+
+/// Looks up a built-in method of a list or map. Methods take the receiver as their first
+/// argument, which is not counted in the arity.
+pub fn method(receiver: &Value, name: &str) -> Option<Native> {
+    let (arity, function): (usize, NativeFn) = match (receiver, name) {
+        (Value::List(_), "len") => (0, list_len),
+        (Value::List(_), "push") => (1, list_push),
+        (Value::List(_), "pop") => (0, list_pop),
+        (Value::List(_), "insert") => (2, list_insert),
+        (Value::List(_), "remove") => (1, list_remove),
+        (Value::List(_), "slice") => (2, list_slice),
+        (Value::Map(_), "len") => (0, map_len),
+        (Value::Map(_), "keys") => (0, map_keys),
+        (Value::Map(_), "values") => (0, map_values),
+        (Value::Map(_), "has") => (1, map_has),
+        (Value::Map(_), "delete") => (1, map_delete),
+        _ => return None,
+    };
+    Some(Native {
+        name: name.to_string(),
+        arity,
+        function,
+    })
+}
+
+/// Converts a Lox index into a position in a list of length `len`. `allow_end` permits the
+/// position one past the last element, as used when inserting or slicing.
+pub fn list_position(len: usize, index: &Value, allow_end: bool) -> Result<usize, String> {
+    let index = match index {
+        Value::Number(index) if index.fract() == 0.0 => *index,
+        _ => return Err("List index must be an integer.".to_string()),
+    };
+
+    let limit = if allow_end { len + 1 } else { len };
+    if index < 0.0 || index >= limit as f64 {
+        return Err(format!(
+            "Index {} out of range for list of length {}.",
+            index, len
+        ));
+    }
+    Ok(index as usize)
+}
+
+fn list_receiver(arguments: &[Value]) -> &Rc<RefCell<Vec<Value>>> {
+    match &arguments[0] {
+        Value::List(list) => list,
+        value => panic!("List method called on {:?}", value),
+    }
+}
+
+fn list_len(arguments: &[Value]) -> Result<Value, String> {
+    Ok(Value::Number(list_receiver(arguments).borrow().len() as f64))
+}
+
+fn list_push(arguments: &[Value]) -> Result<Value, String> {
+    list_receiver(arguments)
+        .borrow_mut()
+        .push(arguments[1].clone());
+    Ok(Value::Nil)
+}
+
+fn list_pop(arguments: &[Value]) -> Result<Value, String> {
+    list_receiver(arguments)
+        .borrow_mut()
+        .pop()
+        .ok_or_else(|| "Can't pop from an empty list.".to_string())
+}
+
+fn list_insert(arguments: &[Value]) -> Result<Value, String> {
+    let mut list = list_receiver(arguments).borrow_mut();
+    let index = list_position(list.len(), &arguments[1], true)?;
+    list.insert(index, arguments[2].clone());
+    Ok(Value::Nil)
+}
+
+fn list_remove(arguments: &[Value]) -> Result<Value, String> {
+    let mut list = list_receiver(arguments).borrow_mut();
+    let index = list_position(list.len(), &arguments[1], false)?;
+    Ok(list.remove(index))
+}
+
+fn list_slice(arguments: &[Value]) -> Result<Value, String> {
+    let list = list_receiver(arguments).borrow();
+    let start = list_position(list.len(), &arguments[1], true)?;
+    let end = list_position(list.len(), &arguments[2], true)?;
+    if start > end {
+        return Err("Slice start can't be after its end.".to_string());
+    }
+    Ok(Value::List(Rc::new(RefCell::new(
+        list[start..end].to_vec(),
+    ))))
+}
+
+fn map_receiver(arguments: &[Value]) -> &Rc<RefCell<Map>> {
+    match &arguments[0] {
+        Value::Map(map) => map,
+        value => panic!("Map method called on {:?}", value),
+    }
+}
+
+fn map_len(arguments: &[Value]) -> Result<Value, String> {
+    Ok(Value::Number(map_receiver(arguments).borrow().len() as f64))
+}
+
+fn map_keys(arguments: &[Value]) -> Result<Value, String> {
+    let keys = map_receiver(arguments).borrow().keys();
+    Ok(Value::List(Rc::new(RefCell::new(keys))))
+}
+
+fn map_values(arguments: &[Value]) -> Result<Value, String> {
+    let values = map_receiver(arguments).borrow().values();
+    Ok(Value::List(Rc::new(RefCell::new(values))))
+}
+
+fn map_has(arguments: &[Value]) -> Result<Value, String> {
+    let has = map_receiver(arguments).borrow().has(&arguments[1])?;
+    Ok(Value::Bool(has))
+}
+
+fn map_delete(arguments: &[Value]) -> Result<Value, String> {
+    let deleted = map_receiver(arguments).borrow_mut().delete(&arguments[1])?;
+    Ok(Value::Bool(deleted))
+}
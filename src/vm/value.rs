@@ -0,0 +1,312 @@
+use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc};
+
+use crate::interpreter::RuntimeError;
+
+use super::chunk::Chunk;
+
+/// A runtime value of the bytecode VM. Heap objects are reference counted and compared by
+/// identity, except strings which compare by content.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(Rc<str>),
+    Function(Rc<Function>),
+    Native(Rc<Native>),
+    Closure(Rc<Closure>),
+    Class(Rc<Class>),
+    Instance(Rc<Instance>),
+    BoundMethod(Rc<BoundMethod>),
+    BoundNative(Rc<BoundNative>),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<Map>>),
+    /// An error on its way out of a `try` statement, held while the statement's `finally` block
+    /// runs so it can be raised again afterwards. It only ever sits in a slot scripts can't name.
+    Unwinding(Rc<RuntimeError>),
+}
+
+impl Value {
+    pub fn is_falsey(&self) -> bool {
+        matches!(self, Value::Nil | Value::Bool(false))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Native(a), Value::Native(b)) => Rc::ptr_eq(a, b),
+            (Value::Closure(a), Value::Closure(b)) => Rc::ptr_eq(a, b),
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            (Value::BoundMethod(a), Value::BoundMethod(b)) => Rc::ptr_eq(a, b),
+            (Value::BoundNative(a), Value::BoundNative(b)) => Rc::ptr_eq(a, b),
+            (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Nil => f.write_str("nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::String(s) => f.write_str(s),
+            Value::Class(class) => f.write_str(&class.name),
+            Value::Instance(instance) => write!(f, "{} instance", instance.class.name),
+            Value::List(list) => display_list(f, list),
+            Value::Map(map) => display_map(f, map),
+            Value::Function(function) => display_function(f, function),
+            Value::Closure(closure) => display_function(f, &closure.function),
+            Value::BoundMethod(bound) => display_function(f, &bound.method.function),
+            Value::Native(_) | Value::BoundNative(_) => f.write_str("<native fn>"),
+            Value::Unwinding(_) => f.write_str("<unwinding>"),
+        }
+    }
+}
+
+fn display_function(f: &mut std::fmt::Formatter<'_>, function: &Function) -> std::fmt::Result {
+    match &function.name {
+        Some(name) => write!(f, "<fn {}>", name),
+        None => f.write_str("<fn anonymous>"),
+    }
+}
+
+thread_local! {
+    /// Collections currently being displayed, so one that contains itself prints as `[...]` or
+    /// `{...}` instead of recursing forever.
+    static DISPLAYING: RefCell<Vec<*const ()>> = const { RefCell::new(vec![]) };
+}
+
+/// Writes a collection between `open` and `close`, guarding against cycles through `pointer`.
+fn display_collection(
+    f: &mut std::fmt::Formatter<'_>,
+    pointer: *const (),
+    (open, close): (&str, &str),
+    body: impl FnOnce(&mut std::fmt::Formatter<'_>) -> std::fmt::Result,
+) -> std::fmt::Result {
+    if DISPLAYING.with(|displaying| displaying.borrow().contains(&pointer)) {
+        return write!(f, "{}...{}", open, close);
+    }
+
+    DISPLAYING.with(|displaying| displaying.borrow_mut().push(pointer));
+    let result = f
+        .write_str(open)
+        .and_then(|_| body(f))
+        .and_then(|_| f.write_str(close));
+    DISPLAYING.with(|displaying| displaying.borrow_mut().pop());
+    result
+}
+
+/// Writes a value the way it appears inside a collection, with strings in quotes.
+fn display_element(f: &mut std::fmt::Formatter<'_>, value: &Value) -> std::fmt::Result {
+    match value {
+        Value::String(string) => write!(f, "\"{}\"", string),
+        _ => write!(f, "{}", value),
+    }
+}
+
+fn display_list(
+    f: &mut std::fmt::Formatter<'_>,
+    list: &Rc<RefCell<Vec<Value>>>,
+) -> std::fmt::Result {
+    display_collection(f, Rc::as_ptr(list).cast(), ("[", "]"), |f| {
+        for (i, element) in list.borrow().iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            display_element(f, element)?;
+        }
+        Ok(())
+    })
+}
+
+fn display_map(f: &mut std::fmt::Formatter<'_>, map: &Rc<RefCell<Map>>) -> std::fmt::Result {
+    display_collection(f, Rc::as_ptr(map).cast(), ("{", "}"), |f| {
+        for (i, (key, value)) in map.borrow().entries.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            display_element(f, key)?;
+            f.write_str(": ")?;
+            display_element(f, value)?;
+        }
+        Ok(())
+    })
+}
+
+/// A hashable form of the values allowed as map keys, equal exactly when the values are. NaN
+/// is never equal to itself, so it is rejected as a key.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum MapKey {
+    Nil,
+    Bool(bool),
+    Number(u64),
+    String(Rc<str>),
+}
+
+impl MapKey {
+    fn from_value(value: &Value) -> Result<Self, String> {
+        Ok(match value {
+            Value::Nil => MapKey::Nil,
+            Value::Bool(b) => MapKey::Bool(*b),
+            // -0 and 0 compare equal, so they must hash the same.
+            Value::Number(n) if *n == 0.0 => MapKey::Number(0.0f64.to_bits()),
+            Value::Number(n) if n.is_nan() => return Err("Map keys cannot be NaN.".to_string()),
+            Value::Number(n) => MapKey::Number(n.to_bits()),
+            Value::String(s) => MapKey::String(s.clone()),
+            _ => return Err("Map keys must be strings, numbers, booleans or nil.".to_string()),
+        })
+    }
+}
+
+/// A hash map that remembers insertion order.
+#[derive(Debug, Default)]
+pub struct Map {
+    positions: HashMap<MapKey, usize>,
+    entries: Vec<(Value, Value)>,
+}
+
+impl Map {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn has(&self, key: &Value) -> Result<bool, String> {
+        Ok(self.positions.contains_key(&MapKey::from_value(key)?))
+    }
+
+    pub fn get(&self, key: &Value) -> Result<Value, String> {
+        match self.positions.get(&MapKey::from_value(key)?) {
+            Some(&position) => Ok(self.entries[position].1.clone()),
+            None => match key {
+                Value::String(key) => Err(format!("Undefined key \"{}\".", key)),
+                _ => Err(format!("Undefined key {}.", key)),
+            },
+        }
+    }
+
+    /// Inserts or updates an entry. Updating keeps the key's original position.
+    pub fn set(&mut self, key: Value, value: Value) -> Result<(), String> {
+        let map_key = MapKey::from_value(&key)?;
+        match self.positions.get(&map_key) {
+            Some(&position) => self.entries[position].1 = value,
+            None => {
+                self.positions.insert(map_key, self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes an entry, returning whether the key was present.
+    pub fn delete(&mut self, key: &Value) -> Result<bool, String> {
+        let position = match self.positions.remove(&MapKey::from_value(key)?) {
+            Some(position) => position,
+            None => return Ok(false),
+        };
+
+        self.entries.remove(position);
+        for later in self.positions.values_mut() {
+            if *later > position {
+                *later -= 1;
+            }
+        }
+        Ok(true)
+    }
+
+    pub fn keys(&self) -> Vec<Value> {
+        self.entries.iter().map(|(key, _)| key.clone()).collect()
+    }
+
+    pub fn values(&self) -> Vec<Value> {
+        self.entries
+            .iter()
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Function {
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+    /// `None` for the top-level script and anonymous functions.
+    pub name: Option<String>,
+}
+
+pub type NativeFn = fn(&[Value]) -> Result<Value, String>;
+
+pub struct Native {
+    pub name: String,
+    pub arity: usize,
+    pub function: NativeFn,
+}
+
+impl std::fmt::Debug for Native {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Native").field(&self.name).finish()
+    }
+}
+
+/// A captured variable. It points at a stack slot while the variable is still live and holds
+/// the value itself once the enclosing function has returned.
+#[derive(Debug)]
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl std::fmt::Debug for Closure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Closure").field(&self.function.name).finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct Class {
+    pub name: String,
+    pub methods: RefCell<HashMap<Rc<str>, Rc<Closure>>>,
+}
+
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: RefCell<HashMap<Rc<str>, Value>>,
+}
+
+impl std::fmt::Debug for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Instance").field(&self.class.name).finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Rc<Closure>,
+}
+
+/// A built-in method of a built-in type, such as `push` on a list, bound to its receiver.
+#[derive(Debug)]
+pub struct BoundNative {
+    pub receiver: Value,
+    pub method: Rc<Native>,
+}
@@ -0,0 +1,75 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+/// Scripts whose output can't be compared: the benchmark prints how long it took.
+const SKIPPED: &[&str] = &["bench.lox"];
+
+/// The exit code for a script that stopped with a runtime error.
+const RUNTIME_ERROR: i32 = 70;
+
+fn scripts(directory: &str) -> Vec<PathBuf> {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join(directory);
+    let mut scripts: Vec<_> = fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "lox"))
+        .filter(|path| !SKIPPED.iter().any(|skipped| path.ends_with(skipped)))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty());
+    scripts
+}
+
+/// Runs `script` on both backends, checking they print the same and exit the same way, and
+/// returns the tree-walker's output.
+fn run_on_both_backends(script: &Path) -> Output {
+    let run = |arguments: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_lox"))
+            .args(arguments)
+            .arg(script)
+            .output()
+            .unwrap()
+    };
+    let tree_walker = run(&[]);
+    let vm = run(&["--vm"]);
+
+    let name = script.display();
+    assert_eq!(tree_walker.status, vm.status, "{}", name);
+    assert_eq!(
+        String::from_utf8_lossy(&tree_walker.stdout),
+        String::from_utf8_lossy(&vm.stdout),
+        "{}",
+        name
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&tree_walker.stderr),
+        String::from_utf8_lossy(&vm.stderr),
+        "{}",
+        name
+    );
+    tree_walker
+}
+
+#[test]
+fn scripts_behave_the_same_on_both_backends() {
+    for script in scripts("tests") {
+        let output = run_on_both_backends(&script);
+        assert!(output.status.success(), "{} failed", script.display());
+    }
+}
+
+#[test]
+fn runtime_errors_are_reported_the_same_on_both_backends() {
+    for script in scripts("tests/errors") {
+        let output = run_on_both_backends(&script);
+        assert_eq!(
+            output.status.code(),
+            Some(RUNTIME_ERROR),
+            "{} didn't stop with a runtime error",
+            script.display()
+        );
+    }
+}
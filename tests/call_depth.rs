@@ -0,0 +1,125 @@
+#[macro_use]
+mod common;
+
+use lox::{run_with_call_depth, vm::Vm, Interpreter, Lox, LoxError, Value};
+use std::{io::Write, process::Command};
+
+const DEPTH: &str = "fun depth(n) {
+  if (n == 0) return 0;
+  return depth(n - 1) + 1;
+}
+";
+
+fn stack_overflow(result: Result<impl std::fmt::Debug, LoxError>) -> usize {
+    let error = expect_error!(result, LoxError::Runtime);
+    assert!(error.to_string().starts_with("Stack overflow."));
+    error.trace().len()
+}
+
+#[test]
+fn both_backends_allow_exactly_the_limit() {
+    let mut lox = Lox::new();
+    lox.interpreter_mut().set_max_call_depth(10);
+    let mut vm = Vm::new();
+    vm.set_max_call_depth(10);
+
+    // `depth(n)` makes n + 1 nested calls.
+    let within = format!("{}depth(9);", DEPTH);
+    assert_eq!(lox.eval(&within).unwrap(), Value::Number(9.0));
+    vm.interpret(&within).unwrap();
+
+    let beyond = format!("{}depth(10);", DEPTH);
+    let tree_walker_frames = stack_overflow(lox.eval(&beyond));
+    let vm_frames = stack_overflow(vm.interpret(&beyond));
+    assert_eq!(tree_walker_frames, vm_frames);
+    // Every active call plus the script.
+    assert_eq!(vm_frames, 11);
+}
+
+#[test]
+fn the_default_limit_fits_deep_recursion() {
+    let source = format!("{}depth(1000);", DEPTH);
+    let result = run_with_call_depth(Interpreter::DEFAULT_MAX_CALL_DEPTH, || {
+        Lox::new()
+            .eval(&source)
+            .map(|value| value.to_string())
+            .map_err(|error| error.to_string())
+    })
+    .unwrap();
+    assert_eq!(result, Ok("1000".to_string()));
+    Vm::new().interpret(&source).unwrap();
+}
+
+#[test]
+fn max_depth_applies_to_the_vm() {
+    let path = std::env::temp_dir().join("lox_call_depth_max_depth.lox");
+    let mut script = std::fs::File::create(&path).unwrap();
+    write!(script, "{}depth(20);", DEPTH).unwrap();
+
+    for arguments in [&["--max-depth", "10"][..], &["--vm", "--max-depth", "10"]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_lox"))
+            .args(arguments)
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(70), "{:?}", arguments);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.starts_with("Stack overflow."), "{:?}", arguments);
+    }
+}
+
+/// A recursive `f(n)` whose body nests `blocks` blocks around `additions` nested additions, so
+/// every Lox call nests far more native frames than a plain recursive call.
+fn nested_recursion(blocks: usize, additions: usize) -> String {
+    let mut body = format!(
+        "return {}f(n - 1){};",
+        "(1 + ".repeat(additions),
+        ")".repeat(additions)
+    );
+    for _ in 0..blocks {
+        body = format!("{{ {} }}", body);
+    }
+    format!("fun f(n) {{\n  if (n == 0) return 0;\n  {}\n}}\n", body)
+}
+
+#[test]
+fn nested_bodies_overflow_cleanly_within_the_default_limit() {
+    let function = nested_recursion(60, 60);
+    let uncaught = format!("{}f(1000);", function);
+    let caught = format!(
+        "{}var message; try {{ f(1000); }} catch (error) {{ message = error.message; }} message;",
+        function
+    );
+    let (uncaught, caught) = run_with_call_depth(Interpreter::DEFAULT_MAX_CALL_DEPTH, || {
+        let uncaught = stack_overflow(Lox::new().eval(&uncaught));
+        let caught = Lox::new().eval(&caught).map(|value| value.to_string());
+        (uncaught, caught.map_err(|error| error.to_string()))
+    })
+    .unwrap();
+    // The script and at least one call of `f`.
+    assert!(uncaught >= 2);
+    assert_eq!(caught, Ok("Stack overflow.".to_string()));
+
+    // The VM doesn't recurse on the native stack, so it gets all the way down.
+    Vm::new()
+        .interpret(&format!("{}f(1000);", function))
+        .unwrap();
+}
+
+#[test]
+fn nesting_too_deep_to_parse_is_an_error() {
+    for source in [
+        format!("print {}1{};", "(".repeat(5000), ")".repeat(5000)),
+        format!("{}print 1;{}", "{".repeat(5000), "}".repeat(5000)),
+        format!("{}print 1;", "if (true) ".repeat(5000)),
+        format!("print {}1;", "-".repeat(5000)),
+    ] {
+        for result in [
+            Lox::new().eval(&source).map(|_| ()),
+            Vm::new().interpret(&source),
+        ] {
+            let errors = expect_error!(result, LoxError::Parse);
+            assert_eq!(errors[0].message, "Too much nesting.");
+        }
+    }
+}
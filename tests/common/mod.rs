@@ -0,0 +1,14 @@
+/// Unwraps the error inside `variant` that `result` is expected to fail with, and panics with
+/// whatever it produced instead otherwise. For example
+/// `expect_error!(lox.eval(source), LoxError::Runtime)` gives the `RuntimeError`.
+macro_rules! expect_error {
+    ($result:expr, $variant:path) => {
+        match $result {
+            Err($variant(error)) => error,
+            other => panic!(
+                concat!("expected ", stringify!($variant), ", got {:?}"),
+                other
+            ),
+        }
+    };
+}
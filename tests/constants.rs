@@ -0,0 +1,98 @@
+use lox::{vm::Compiler, Scanner};
+use std::{fs, process::Command};
+
+/// Runs `source` on both backends and checks that each prints `expected`.
+fn assert_both_backends_print(name: &str, source: &str, expected: &str) {
+    let path = std::env::temp_dir().join(format!("lox_constants_{}.lox", name));
+    fs::write(&path, source).unwrap();
+
+    for arguments in [&[][..], &["--vm"]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_lox"))
+            .args(arguments)
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{} {:?}: {}",
+            name,
+            arguments,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            expected,
+            "{} {:?}",
+            name,
+            arguments
+        );
+    }
+}
+
+#[test]
+fn many_globals() {
+    let mut source: String = (0..300).map(|n| format!("var v{} = {};\n", n, n)).collect();
+    source.push_str("print v0 + v299;\n");
+    // Every instruction that names a constant, now with indices past the first 256.
+    source.push_str(
+        "class A {
+  init() { this.x = 1; }
+  get() { return this.x; }
+}
+class B < A {
+  get() { return super.get() + 1; }
+  parent() { return super.get; }
+}
+var b = B();
+b.x = 41;
+print b.get();
+print b.parent()();
+fun read() { return b.x; }
+print read();
+",
+    );
+    assert_both_backends_print("globals", &source, "299\n42\n41\n41\n");
+}
+
+#[test]
+fn large_list_and_map_literals() {
+    let elements: Vec<_> = (0..300).map(|n| n.to_string()).collect();
+    let entries: Vec<_> = (0..300).map(|n| format!("\"k{}\": {}", n, n)).collect();
+    let source = format!(
+        "var l = [{}];\nvar m = {{{}}};\nprint l.len();\nprint l[299];\nprint m.len();\nprint m[\"k299\"];\n",
+        elements.join(", "),
+        entries.join(", ")
+    );
+    assert_both_backends_print("literals", &source, "300\n299\n300\n299\n");
+}
+
+#[test]
+fn long_interpolations() {
+    let parts: String = (0..300).map(|n| format!("${{{}}} ", n)).collect();
+    let source = format!("print \"{}\";\n", parts);
+    let expected: String = (0..300).map(|n| format!("{} ", n)).collect();
+    assert_both_backends_print("interpolation", &source, &format!("{}\n", expected));
+}
+
+#[test]
+fn many_distinct_constants_in_a_function() {
+    let body: String = (0..300).map(|n| format!("s = s + {};\n", n)).collect();
+    let source = format!(
+        "fun f() {{\nvar s = 0;\n{}return s;\n}}\nprint f();\n",
+        body
+    );
+    assert_both_backends_print("function", &source, "44850\n");
+}
+
+#[test]
+fn repeated_constants_share_a_slot() {
+    let mut source = "var x = 0;\n".to_string();
+    source.push_str(&"x = x + 1;\nprint \"x\";\n".repeat(300));
+    assert_both_backends_print("repeated", &source, &"x\n".repeat(300));
+
+    let (tokens, errors) = Scanner::new(&source).scan_tokens();
+    assert!(errors.is_empty());
+    let function = Compiler::new(tokens).compile().unwrap();
+    // The name `x`, which doubles as the printed string, and the numbers 0 and 1.
+    assert_eq!(function.chunk.constants.len(), 3);
+}
@@ -0,0 +1,44 @@
+use lox::{run_source, Lox, LoxError, Value};
+
+#[test]
+fn eval_returns_the_final_expression_value() {
+    let mut lox = Lox::new();
+    assert_eq!(lox.eval("1 + 2;").unwrap(), Value::Number(3.0));
+    assert_eq!(lox.eval("var x = 1;").unwrap(), Value::None);
+    assert_eq!(
+        lox.eval("print 1; \"done\";").unwrap(),
+        Value::String("done".into())
+    );
+}
+
+#[test]
+fn definitions_persist_across_eval_calls() {
+    let mut lox = Lox::new();
+    lox.eval("fun double(x) { return x * 2; }").unwrap();
+    lox.eval("var base = 20;").unwrap();
+    assert_eq!(lox.eval("double(base) + 2;").unwrap(), Value::Number(42.0));
+}
+
+#[test]
+fn globals_survive_a_runtime_error() {
+    let mut lox = Lox::new();
+    let error = lox.eval("var kept = 1; kept + nil;").unwrap_err();
+    assert!(matches!(error, LoxError::Runtime(_)));
+    assert_eq!(lox.eval("kept;").unwrap(), Value::Number(1.0));
+}
+
+#[test]
+fn run_source_uses_a_fresh_session() {
+    assert_eq!(
+        run_source("var a = 4; a * a;").unwrap(),
+        Value::Number(16.0)
+    );
+    assert!(matches!(run_source("a;"), Err(LoxError::Runtime(_))));
+}
+
+#[test]
+fn compile_errors_are_reported_by_phase() {
+    assert!(matches!(run_source("\"open"), Err(LoxError::Scan(_))));
+    assert!(matches!(run_source("var;"), Err(LoxError::Parse(_))));
+    assert!(matches!(run_source("return 1;"), Err(LoxError::Resolve(_))));
+}
@@ -0,0 +1,23 @@
+#[macro_use]
+mod common;
+
+use lox::{run_source, vm::Vm, LoxError};
+
+#[test]
+fn empty_interpolation_is_a_scan_error() {
+    for source in [
+        "print \"${}\";",
+        "print \"${}${1}\";",
+        "print \"${1}${}\";",
+        "print \"\"\"a ${} b\"\"\";",
+    ] {
+        for errors in [
+            expect_error!(run_source(source), LoxError::Scan),
+            expect_error!(Vm::new().interpret(source), LoxError::Scan),
+        ] {
+            assert_eq!(errors.len(), 1, "{}", source);
+            assert_eq!(errors[0].message, "Expect expression in interpolation.");
+            assert_eq!(&source[errors[0].span.clone()], "${}");
+        }
+    }
+}
@@ -0,0 +1,35 @@
+#[macro_use]
+mod common;
+
+use lox::{run_source, vm::Vm, LoxError, Value};
+
+#[test]
+fn zero_and_negative_zero_are_the_same_key() {
+    let source = "var m = {}; m[0] = 1; m[-0] = 2; m.len() * 10 + m[0];";
+    assert_eq!(run_source(source).unwrap(), Value::Number(12.0));
+}
+
+#[test]
+fn nan_is_rejected_as_a_key() {
+    for source in [
+        "var m = {}; m[0/0] = 1;",
+        "var m = {0/0: 1};",
+        "var m = {}; m.has(0/0);",
+        "var m = {}; m[0/0];",
+    ] {
+        assert!(
+            expect_error!(run_source(source), LoxError::Runtime)
+                .to_string()
+                .starts_with("Map keys cannot be NaN."),
+            "tree-walker accepted NaN in {}",
+            source
+        );
+        assert!(
+            expect_error!(Vm::new().interpret(source), LoxError::Runtime)
+                .to_string()
+                .starts_with("Map keys cannot be NaN."),
+            "VM accepted NaN in {}",
+            source
+        );
+    }
+}
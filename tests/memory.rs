@@ -0,0 +1,122 @@
+use lox::{Lox, Value};
+
+/// Runs `source` under a 4 KiB memory limit and returns the message of the error it catches.
+fn caught_message(source: &str) -> Value {
+    let mut lox = Lox::new();
+    lox.interpreter_mut().set_memory_limit(Some(4096));
+    lox.eval(&format!(
+        "var message; try {{ {} }} catch (e) {{ message = e.message; }}",
+        source
+    ))
+    .unwrap();
+    lox.eval("message;").unwrap()
+}
+
+fn current(lox: &Lox) -> usize {
+    lox.interpreter().memory_stats().current
+}
+
+#[test]
+fn growing_a_string_runs_out_of_memory() {
+    assert_eq!(
+        caught_message("var s = \"x\"; while (true) s = s + s;"),
+        Value::String("Out of memory.".into())
+    );
+}
+
+#[test]
+fn pushing_onto_a_list_runs_out_of_memory() {
+    assert_eq!(
+        caught_message("var list = []; while (true) list.push(1);"),
+        Value::String("Out of memory.".into())
+    );
+}
+
+#[test]
+fn interpolating_a_string_runs_out_of_memory() {
+    assert_eq!(
+        caught_message("var s = \"x\"; while (true) s = \"${s}${s}\";"),
+        Value::String("Out of memory.".into())
+    );
+}
+
+#[test]
+fn stats_track_current_and_peak_use() {
+    let mut lox = Lox::new();
+    let baseline = current(&lox);
+    lox.eval("var list = []; for (var i = 0; i < 100; i = i + 1) list.push(i);")
+        .unwrap();
+    let stats = lox.interpreter().memory_stats();
+    assert!(stats.current > baseline);
+    assert!(stats.peak >= stats.current);
+
+    lox.eval("list = nil;").unwrap();
+    let after = lox.interpreter().memory_stats();
+    assert_eq!(after.peak, stats.peak);
+    assert!(after.current < stats.current);
+}
+
+#[test]
+fn dropped_values_give_their_memory_back() {
+    let mut lox = Lox::new();
+    lox.eval("var keep = nil;").unwrap();
+    let baseline = current(&lox);
+
+    lox.eval(
+        "class Box {} keep = { \"list\": [1, 2, 3], \"text\": \"a\" + \"b\", \"box\": Box() }; keep[\"box\"].field = \"c\" + \"d\";",
+    )
+    .unwrap();
+    assert!(current(&lox) > baseline);
+
+    lox.eval("keep = nil;").unwrap();
+    assert_eq!(current(&lox), baseline);
+}
+
+#[test]
+fn freed_memory_can_be_reused_under_the_limit() {
+    let mut lox = Lox::new();
+    lox.interpreter_mut().set_memory_limit(Some(4096));
+    for _ in 0..10 {
+        lox.eval("var list = []; for (var i = 0; i < 50; i = i + 1) list.push(i); list = nil;")
+            .unwrap();
+    }
+}
+
+#[test]
+fn closures_and_their_scopes_run_out_of_memory() {
+    assert_eq!(
+        caught_message("var f = nil; while (true) { var g = f; f = fun() { var a; return g; }; }"),
+        Value::String("Out of memory.".into())
+    );
+}
+
+#[test]
+fn closures_give_their_memory_back() {
+    let mut lox = Lox::new();
+    lox.eval("fun counter() { var count = 0; return fun() { count = count + 1; return count; }; } var c = nil;")
+        .unwrap();
+    let baseline = current(&lox);
+
+    lox.eval("c = counter(); c();").unwrap();
+    assert!(current(&lox) > baseline);
+
+    lox.eval("c = nil;").unwrap();
+    assert_eq!(current(&lox), baseline);
+}
+
+#[test]
+fn long_chains_are_dropped_without_overflowing_the_stack() {
+    let mut lox = Lox::new();
+    lox.eval(
+        "var f = nil; var head = nil; var list = nil; var map = nil; class Node {}
+         for (var i = 0; i < 100000; i = i + 1) {
+             var g = f; f = fun() { return g; };
+             var node = Node(); node.next = head; head = node;
+             list = [list];
+             map = {\"next\": map};
+         }",
+    )
+    .unwrap();
+    lox.eval("f = nil; head = nil; list = nil; map = nil;")
+        .unwrap();
+}
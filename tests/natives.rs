@@ -0,0 +1,67 @@
+#[macro_use]
+mod common;
+
+use lox::{anyhow, Arity, Lox, LoxError, Value};
+use std::{cell::Cell, rc::Rc};
+
+#[test]
+fn natives_can_capture_host_state() {
+    let mut lox = Lox::new();
+    let count = Rc::new(Cell::new(0));
+    let counter = Rc::clone(&count);
+    lox.interpreter_mut().define_native("tick", 0, move |_, _| {
+        counter.set(counter.get() + 1);
+        Ok(Value::Number(counter.get() as f64))
+    });
+    assert_eq!(
+        lox.eval("tick(); tick(); tick();").unwrap(),
+        Value::Number(3.0)
+    );
+    assert_eq!(count.get(), 3);
+}
+
+#[test]
+fn variadic_natives_accept_extra_arguments() {
+    let mut lox = Lox::new();
+    lox.interpreter_mut()
+        .define_native("sum", Arity::AtLeast(1), |_, arguments| {
+            let mut total = 0.0;
+            for argument in arguments {
+                match argument {
+                    Value::Number(n) => total += n,
+                    _ => anyhow::bail!("sum() expects numbers."),
+                }
+            }
+            Ok(Value::Number(total))
+        });
+    assert_eq!(lox.eval("sum(1);").unwrap(), Value::Number(1.0));
+    assert_eq!(lox.eval("sum(1, 2, 3, 4);").unwrap(), Value::Number(10.0));
+    assert!(matches!(lox.eval("sum();"), Err(LoxError::Runtime(_))));
+}
+
+#[test]
+fn native_errors_become_runtime_errors() {
+    let mut lox = Lox::new();
+    lox.interpreter_mut()
+        .define_native("fail", 0, |_, _| Err(anyhow::anyhow!("Host failure.")));
+    let error = expect_error!(lox.eval("\n\nfail();"), LoxError::Runtime);
+    assert!(error.to_string().starts_with("Host failure."));
+    assert_eq!(error.line(), 3);
+}
+
+#[test]
+fn natives_can_call_back_into_lox() {
+    let mut lox = Lox::new();
+    lox.interpreter_mut()
+        .define_native("twice", 2, |context, arguments| {
+            let once = context.call(&arguments[0], &arguments[1..])?;
+            context.call(&arguments[0], &[once])
+        });
+    lox.eval("fun inc(x) { return x + 1; }").unwrap();
+    assert_eq!(lox.eval("twice(inc, 40);").unwrap(), Value::Number(42.0));
+    assert_eq!(
+        lox.eval("twice(fun(s) { return s + \"!\"; }, \"hi\");")
+            .unwrap(),
+        Value::String("hi!!".into())
+    );
+}
@@ -0,0 +1,97 @@
+#[macro_use]
+mod common;
+
+use lox::{Lox, LoxError, ResourceExhausted, Value};
+use std::time::{Duration, Instant};
+
+#[test]
+fn running_out_of_fuel_stops_an_infinite_loop() {
+    let mut lox = Lox::new();
+    lox.interpreter_mut().set_fuel(Some(1000));
+    assert_eq!(
+        expect_error!(lox.eval("while (true) {}"), LoxError::ResourceExhausted),
+        ResourceExhausted::Fuel
+    );
+    assert_eq!(lox.interpreter().fuel(), Some(0));
+}
+
+#[test]
+fn a_passed_deadline_stops_an_infinite_loop() {
+    let mut lox = Lox::new();
+    lox.interpreter_mut()
+        .set_deadline(Some(Instant::now() - Duration::from_millis(1)));
+    assert_eq!(
+        expect_error!(lox.eval("while (true) {}"), LoxError::ResourceExhausted),
+        ResourceExhausted::Deadline
+    );
+}
+
+#[test]
+fn execution_resumes_on_the_same_globals_after_topping_up() {
+    let mut lox = Lox::new();
+    lox.eval("var total = 0; fun add(n) { total = total + n; }")
+        .unwrap();
+    lox.interpreter_mut().set_fuel(Some(50));
+    expect_error!(
+        lox.eval("for (var i = 1; i <= 1000; i = i + 1) add(i);"),
+        LoxError::ResourceExhausted
+    );
+
+    let partial = match lox.eval("total;").unwrap() {
+        Value::Number(total) => total,
+        other => panic!("expected a number, got {:?}", other),
+    };
+    assert!(partial > 0.0 && partial < 500500.0);
+
+    lox.interpreter_mut().set_fuel(None);
+    lox.eval("total = 0; for (var i = 1; i <= 1000; i = i + 1) add(i);")
+        .unwrap();
+    assert_eq!(lox.eval("total;").unwrap(), Value::Number(500500.0));
+
+    // The calls that were running when the fuel ran out are gone from the stack.
+    let error = expect_error!(lox.eval("add(nil);"), LoxError::Runtime);
+    assert_eq!(error.trace().len(), 2);
+}
+
+#[test]
+fn scripts_cannot_catch_running_out() {
+    let mut lox = Lox::new();
+    lox.eval("var log = [];").unwrap();
+    lox.interpreter_mut().set_fuel(Some(1000));
+    expect_error!(lox.eval(
+        "try { while (true) {} } catch (e) { log.push(\"caught\"); } finally { log.push(\"finally\"); }",
+    ), LoxError::ResourceExhausted);
+
+    lox.interpreter_mut()
+        .set_deadline(Some(Instant::now() - Duration::from_millis(1)));
+    lox.interpreter_mut().set_fuel(None);
+    expect_error!(lox.eval(
+        "try { while (true) {} } catch (e) { log.push(\"caught\"); } finally { log.push(\"finally\"); }",
+    ), LoxError::ResourceExhausted);
+
+    lox.interpreter_mut().set_deadline(None);
+    assert_eq!(lox.eval("log.len();").unwrap(), Value::Number(0.0));
+}
+
+#[test]
+fn running_out_inside_a_native_callback_cannot_be_caught() {
+    let mut lox = Lox::new();
+    lox.interpreter_mut()
+        .define_native("apply", 1, |context, arguments| {
+            context.call(&arguments[0], &[])
+        });
+    lox.eval("var log = [];").unwrap();
+    lox.interpreter_mut().set_fuel(Some(500));
+    assert_eq!(
+        expect_error!(
+            lox.eval(
+                "try { apply(fun() { while (true) {} }); } catch (e) { log.push(e); } finally { log.push(\"finally\"); }",
+            ),
+            LoxError::ResourceExhausted
+        ),
+        ResourceExhausted::Fuel
+    );
+
+    lox.interpreter_mut().set_fuel(None);
+    assert_eq!(lox.eval("log.len();").unwrap(), Value::Number(0.0));
+}
@@ -0,0 +1,67 @@
+#[macro_use]
+mod common;
+
+use lox::{vm::Vm, Lox, LoxError, TraceFrame, Value};
+use std::{cell::RefCell, rc::Rc};
+
+const SOURCE: &str = "fun inner() {
+  return 1 + nil;
+}
+fun outer() {
+  return inner();
+}
+outer();
+";
+
+fn frame(function: &str, line: usize) -> TraceFrame {
+    TraceFrame {
+        function: function.to_string(),
+        line,
+    }
+}
+
+fn trace(result: Result<impl std::fmt::Debug, LoxError>) -> Vec<TraceFrame> {
+    expect_error!(result, LoxError::Runtime).trace().to_vec()
+}
+
+#[test]
+fn runtime_errors_carry_the_call_stack_innermost_first() {
+    let expected = vec![frame("inner", 2), frame("outer", 5), frame("<script>", 7)];
+    assert_eq!(trace(Lox::new().eval(SOURCE)), expected);
+    assert_eq!(trace(Vm::new().interpret(SOURCE)), expected);
+}
+
+#[test]
+fn top_level_errors_have_only_the_script_frame() {
+    let source = "var a = 1;\na();";
+    let expected = vec![frame("<script>", 2)];
+    assert_eq!(trace(Lox::new().eval(source)), expected);
+    assert_eq!(trace(Vm::new().interpret(source)), expected);
+}
+
+#[test]
+fn anonymous_functions_and_initializers_are_named_in_traces() {
+    let source = "class Box {\n  init() {\n    (fun() {\n      nil();\n    })();\n  }\n}\nBox();";
+    let expected = vec![
+        frame("<anonymous>", 4),
+        frame("init", 5),
+        frame("<script>", 8),
+    ];
+    assert_eq!(trace(Lox::new().eval(source)), expected);
+    assert_eq!(trace(Vm::new().interpret(source)), expected);
+}
+
+#[test]
+fn natives_can_read_the_stack_of_their_caller() {
+    let mut lox = Lox::new();
+    let seen = Rc::new(RefCell::new(vec![]));
+    let recorder = Rc::clone(&seen);
+    lox.interpreter_mut()
+        .define_native("where", 0, move |context, _| {
+            *recorder.borrow_mut() = context.stack_trace();
+            Ok(Value::None)
+        });
+
+    lox.eval("fun f() {\n  where();\n}\nf();").unwrap();
+    assert_eq!(*seen.borrow(), vec![frame("f", 2), frame("<script>", 4)]);
+}